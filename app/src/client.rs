@@ -0,0 +1,184 @@
+//! Client side of online play: predicts own movement and smooths out everyone else's.
+//!
+//! The client ticks at [`TICK_RATE`] like the server, sending one input per tick and applying
+//! it to its own player right away. Server states carry the last applied input, so on each one
+//! the own player is reset to the server position and the inputs still in flight are replayed.
+//! Other players are drawn a little in the past, between the two server states around that time.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use protocol::{Delta, Input, Keys, Snapshot};
+
+use crate::sim::{Player, World, TICK_RATE};
+
+/// Ticks other players are drawn behind the latest server state.
+const INTERPOLATION_DELAY: f64 = 2.0;
+/// Server states kept for interpolation.
+const HISTORY: usize = 32;
+/// Most ticks simulated in one frame. After a hiccup the rest is skipped.
+const MAX_STEPS: usize = 5;
+/// Most inputs waiting for acknowledgement. Older ones are forgotten.
+const MAX_PENDING: usize = 2 * TICK_RATE as usize;
+
+/// Player positions at a server tick.
+type State = (u32, BTreeMap<u32, (i32, i32)>);
+
+pub struct Client {
+    pub id: u32,
+    /// Latest server state.
+    world: World,
+    /// Recent server states, oldest first.
+    history: VecDeque<State>,
+    /// Own player with unacknowledged inputs applied.
+    predicted: Option<Player>,
+    /// Sent inputs the server hasn't applied yet.
+    pending: VecDeque<Input>,
+    /// Sequence number of the last sent input.
+    seq: u32,
+    /// Seconds since the last local tick.
+    time: f32,
+    /// Server tick other players are drawn at.
+    render_tick: f64,
+}
+impl Client {
+    /// Start playing in a room currently at `tick`.
+    pub fn new(id: u32, tick: u32) -> Self {
+        Self {
+            id,
+            world: World {
+                tick,
+                ..Default::default()
+            },
+            history: VecDeque::new(),
+            predicted: None,
+            pending: VecDeque::new(),
+            seq: 0,
+            time: 0.0,
+            render_tick: tick as f64,
+        }
+    }
+
+    /// Tick of the latest server state.
+    pub fn tick(&self) -> u32 {
+        self.world.tick
+    }
+
+    /// Advance local time by `delta` seconds with `keys` held. Returns inputs to send.
+    pub fn update(&mut self, delta: f32, keys: Keys) -> Vec<Input> {
+        let step = 1.0 / TICK_RATE as f32;
+        // A bogus or huge delta (first frame, background tab) must not flood the server.
+        let delta = delta.max(0.0).min(MAX_STEPS as f32 * step);
+        let mut inputs = vec![];
+        self.time += delta;
+        while self.time >= step {
+            if inputs.len() == MAX_STEPS {
+                self.time = 0.0;
+                break;
+            }
+            self.time -= step;
+            self.seq += 1;
+            let input = Input {
+                seq: self.seq,
+                keys,
+            };
+            if let Some(x) = &mut self.predicted {
+                x.advance(keys);
+            }
+            if self.pending.len() == MAX_PENDING {
+                self.pending.pop_front();
+            }
+            self.pending.push_back(input);
+            inputs.push(input);
+        }
+
+        if let Some((latest, _)) = self.history.back() {
+            let latest = *latest as f64;
+            let target = latest - INTERPOLATION_DELAY;
+            self.render_tick += delta as f64 * TICK_RATE as f64;
+            // Fell too far behind or ahead, e.g. after lag. Jump instead of catching up slowly.
+            if (self.render_tick - target).abs() > INTERPOLATION_DELAY * 2.0 {
+                self.render_tick = target;
+            }
+            self.render_tick = self.render_tick.min(latest);
+        }
+        inputs
+    }
+
+    pub fn apply_snapshot(&mut self, snapshot: &Snapshot) {
+        self.world.apply_snapshot(snapshot);
+        self.received(snapshot.ack);
+    }
+
+    /// Returns `false` if the delta is for another tick and was dropped.
+    pub fn apply_delta(&mut self, delta: &Delta) -> bool {
+        if !self.world.apply_delta(delta) {
+            return false;
+        }
+        self.received(delta.ack);
+        true
+    }
+
+    /// Reconcile with a new server state where inputs up to `ack` are applied.
+    fn received(&mut self, ack: u32) {
+        let positions = self
+            .world
+            .players
+            .iter()
+            .map(|(id, x)| (*id, (x.x, x.y)))
+            .collect();
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back((self.world.tick, positions));
+
+        while self.pending.front().is_some_and(|x| x.seq <= ack) {
+            self.pending.pop_front();
+        }
+        self.predicted = self.world.players.get(&self.id).map(|x| {
+            let mut x = Player {
+                x: x.x,
+                y: x.y,
+                ..Default::default()
+            };
+            for input in &self.pending {
+                x.advance(input.keys);
+            }
+            x
+        });
+    }
+
+    /// Where to draw every player: predicted for self, interpolated for others.
+    pub fn positions(&self) -> Vec<(u32, i32, i32)> {
+        let after = self
+            .history
+            .iter()
+            .position(|(tick, _)| *tick as f64 >= self.render_tick);
+        let (before, after) = match after {
+            Some(0) => (&self.history[0], &self.history[0]),
+            Some(x) => (&self.history[x - 1], &self.history[x]),
+            None => match self.history.back() {
+                Some(x) => (x, x),
+                None => return vec![],
+            },
+        };
+        let t = match after.0.wrapping_sub(before.0) {
+            0 => 1.0,
+            x => (self.render_tick - before.0 as f64) / x as f64,
+        };
+        let lerp = |a: i32, b: i32| a + ((b - a) as f64 * t).round() as i32;
+
+        let mut positions: Vec<_> = after
+            .1
+            .iter()
+            .filter(|(id, _)| **id != self.id)
+            .map(|(id, (x, y))| match before.1.get(id) {
+                Some((x0, y0)) => (*id, lerp(*x0, *x), lerp(*y0, *y)),
+                None => (*id, *x, *y),
+            })
+            .collect();
+        if let Some(x) = &self.predicted {
+            positions.push((self.id, x.x, x.y));
+        }
+        positions
+    }
+}
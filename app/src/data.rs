@@ -0,0 +1,40 @@
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::util::Result;
+
+/// `data/demo.toml`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Demo {
+    pub sprite: DemoSprite,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DemoSprite {
+    pub size: u32,
+    pub amplitude: f32,
+}
+
+/// A data file (`.toml`, `.json` or `.ron`), parsed into the struct for its path once loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Data {
+    Demo(Demo),
+}
+impl Data {
+    /// Parse the data file at resource path `path`.
+    pub fn load(path: &str, mime: &str, bytes: &[u8]) -> Result<Self> {
+        let text = std::str::from_utf8(bytes)?;
+        Ok(match path {
+            "/data/demo.toml" => Self::Demo(parse(mime, text)?),
+            x => return Err(format!("No struct for data file '{x}'").into()),
+        })
+    }
+}
+
+fn parse<T: DeserializeOwned>(mime: &str, text: &str) -> Result<T> {
+    Ok(match mime {
+        "application/toml" => toml::from_str(text)?,
+        "application/json" => serde_json::from_str(text)?,
+        "application/ron" => ron::from_str(text)?,
+        x => return Err(format!("Unsupported data type '{x}'").into()),
+    })
+}
@@ -1,11 +1,14 @@
 use std::{marker::PhantomData, ops::Mul, time::SystemTime};
 
-use assets::Resources;
+use assets::{lang::Locale, Resources};
+use protocol::{ClientMessage, Keys, ServerMessage, VERSION};
 
 use crate::{
+    client::Client,
+    data::Data,
     interface::{Interface, InterfaceExt},
     util::Result,
-    Event, GenericKey,
+    Control, Event, GenericKey, KeyState,
 };
 
 pub enum Flow {
@@ -15,33 +18,148 @@ pub enum Flow {
 }
 
 pub struct Game<I: Interface + ?Sized> {
+    locale: Locale,
+    /// Own player id once connected.
+    id: Option<u32>,
+    /// Online play state once in a room.
+    client: Option<Client>,
+    keys: Keys,
     _phantom: PhantomData<I>,
 }
 impl<I: Interface + ?Sized> Game<I> {
+    /// Language all text is displayed in.
+    pub fn locale(&self) -> Locale {
+        self.locale
+    }
+
+    /// Switch the language. Takes effect on next redraw.
+    pub fn set_locale(&mut self, locale: Locale) {
+        info!("Switching locale to '{}'", locale.code());
+        self.locale = locale;
+    }
+
+    fn control(&mut self, control: Control, state: KeyState) {
+        let key = match control {
+            Control::Up => Keys::UP,
+            Control::Down => Keys::DOWN,
+            Control::Left => Keys::LEFT,
+            Control::Right => Keys::RIGHT,
+        };
+        self.keys.set(key, state == KeyState::Pressed);
+    }
+
+    fn message(&mut self, int: &mut I, message: ServerMessage) {
+        match message {
+            ServerMessage::Welcome { id } => {
+                info!("Connected to server as client {id}");
+                self.id = Some(id);
+                int.send(ClientMessage::QuickMatch);
+            }
+            ServerMessage::Joined {
+                room,
+                code,
+                host,
+                tick,
+            } => {
+                match code {
+                    Some(code) => info!("Joined private room '{}' ({code})", room.name),
+                    None => info!("Joined room '{}'", room.name),
+                }
+                info!("Room host is client {host}");
+                self.client = self.id.map(|id| Client::new(id, tick));
+            }
+            ServerMessage::Left => self.client = None,
+            ServerMessage::Kicked(x) => {
+                info!("Kicked from room {x}");
+                self.client = None;
+            }
+            ServerMessage::Host(x) => info!("Room host is now client {x}"),
+            ServerMessage::Rooms(x) => debug!("Rooms: {x:?}"),
+            ServerMessage::Snapshot(x) => {
+                if let Some(client) = &mut self.client {
+                    client.apply_snapshot(&x);
+                }
+            }
+            ServerMessage::Delta(x) => {
+                if let Some(client) = &mut self.client {
+                    if !client.apply_delta(&x) {
+                        debug!("Dropped delta for tick {} (at {})", x.base, client.tick());
+                    }
+                }
+            }
+            ServerMessage::Chat { from, text } => info!("<{from}> {text}"),
+            ServerMessage::Ping(x) => int.send(ClientMessage::Pong(x)),
+            ServerMessage::Pong(_) => (),
+            ServerMessage::Error { code, message } if code.is_fatal() => {
+                error!("Server error ({code:?}): {message}")
+            }
+            ServerMessage::Error { code, message } => warn!("Refused ({code:?}): {message}"),
+        }
+    }
+
     pub fn process_events(
         &mut self,
         int: &mut I,
-        res: &Resources<I::Tex, I::Font>,
+        res: &Resources<I::Tex, I::Font, Data>,
     ) -> Result<Flow> {
         let mut flow = Flow::Continue;
         while let Some(x) = int.poll() {
             match x {
                 Event::Quit => return Ok(Flow::Exit),
                 Event::Input(GenericKey::Esc) => return Ok(Flow::Exit),
-                Event::Redraw(_) => {
+                Event::Redraw(delta) => {
+                    if let Some(client) = &mut self.client {
+                        for x in client.update(delta, self.keys) {
+                            int.send(ClientMessage::Input(x));
+                        }
+                    }
+                    let Data::Demo(demo) = &res.data_demo_toml;
                     let shift = int
                         .now()
                         .duration_since(SystemTime::UNIX_EPOCH)
                         .unwrap()
                         .as_secs_f32()
                         .sin()
-                        .mul(100.0)
+                        .mul(demo.sprite.amplitude)
                         .ceil() as i32;
                     int.clear(0x000000ff);
-                    int.fill_text(0xffffffff, 20, (100, 100), &res.hack_regular_ttf, "Hello!");
-                    int.copy_center(res.terrain_sprites_csv_sand(), (120 + shift, 120, 64, 64));
+                    int.fill_text(
+                        0xffffffff,
+                        20,
+                        (100, 100),
+                        &res.hack_regular_ttf,
+                        self.locale.hello(),
+                    );
+                    int.copy_center(
+                        res.terrain_sprites_csv_sand(),
+                        (120 + shift, 120, demo.sprite.size, demo.sprite.size),
+                    );
+                    let center = int.size();
+                    let positions = self.client.as_ref().map(Client::positions);
+                    for (id, x, y) in positions.unwrap_or_default() {
+                        let sprite = if Some(id) == self.id {
+                            res.terrain_sprites_csv_sand()
+                        } else {
+                            res.terrain_sprites_csv_grass()
+                        };
+                        int.copy_center(
+                            sprite,
+                            (center.0 as i32 / 2 + x, center.1 as i32 / 2 + y, 32, 32),
+                        );
+                    }
                     flow = Flow::Redraw;
                 }
+                Event::Control(control, state) => self.control(control, state),
+                Event::Connected => int.send(ClientMessage::Hello {
+                    version: VERSION,
+                    name: String::new(),
+                }),
+                Event::Disconnected => {
+                    warn!("Disconnected from server");
+                    self.id = None;
+                    self.client = None;
+                }
+                Event::Message(x) => self.message(int, x),
                 _ => (),
             }
         }
@@ -51,6 +169,10 @@ impl<I: Interface + ?Sized> Game<I> {
 impl<I: Interface + ?Sized> Default for Game<I> {
     fn default() -> Self {
         Self {
+            locale: Default::default(),
+            id: None,
+            client: None,
+            keys: Default::default(),
             _phantom: PhantomData,
         }
     }
@@ -8,6 +8,7 @@ use std::{
 };
 
 use assets::Sprite;
+use protocol::{ClientMessage, ServerMessage};
 
 #[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
 pub struct Rgba(pub u8, pub u8, pub u8, pub u8);
@@ -146,6 +147,15 @@ pub enum CursorButton<I: Interface + ?Sized> {
     Other(I::OtherCursorButton),
 }
 
+/// Movement controls, mapped from whatever keys the platform uses.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Control {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum GenericKey {
     /// Usually the enter key.
@@ -170,6 +180,8 @@ pub enum Event<I: Interface + ?Sized> {
     Focused(bool),
     /// Generic keypress input.
     Input(GenericKey),
+    /// A movement control was pressed or released.
+    Control(Control, KeyState),
     /// A cursor has been moved.
     CursorMove(I::CursorId, ScreenPos),
     /// A cursor button state has changed.
@@ -182,6 +194,12 @@ pub enum Event<I: Interface + ?Sized> {
     Terminate,
     /// Window needs to be redrawn
     Redraw(f32),
+    /// Connected to the game server.
+    Connected,
+    /// Connection to the game server was lost.
+    Disconnected,
+    /// Game server sent a message.
+    Message(ServerMessage),
 }
 
 pub trait Interface {
@@ -203,6 +221,8 @@ pub trait Interface {
     fn held(&self, key: &Self::Key) -> bool;
     /// Framerate to attempt to average.
     fn target_framerate(&self) -> u16;
+    /// Send a message to the game server. Dropped if not connected.
+    fn send(&mut self, message: ClientMessage);
 
     /// Render text on screen.
     fn fill_text_raw(
@@ -1,20 +1,24 @@
 #[macro_use]
 extern crate log;
 
+pub mod client;
+pub mod data;
 pub mod game;
 pub mod interface;
+pub mod sim;
 pub mod util;
 
 pub use interface::*;
 
 use assets::Resources;
+use data::Data;
 use game::{Flow, Game};
 use util::Result;
 
 pub struct Application<I: Interface> {
     pub interface: I,
     pub game: Game<I>,
-    pub resources: Resources<I::Tex, I::Font>,
+    pub resources: Resources<I::Tex, I::Font, Data>,
 }
 impl<I: Interface> Application<I> {
     pub fn tick(&mut self) -> Result<Flow> {
@@ -0,0 +1,149 @@
+//! Game rules, independent of rendering so the server can run them too.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use protocol::{Delta, Entity, Input, Keys, Snapshot};
+
+/// Simulation steps per second.
+pub const TICK_RATE: u32 = 30;
+/// Distance a player moves per tick.
+pub const SPEED: i32 = 4;
+/// Most inputs queued per player. Older ones are dropped when a client sends too fast.
+pub const MAX_QUEUED_INPUTS: usize = 4;
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Player {
+    pub x: i32,
+    pub y: i32,
+    pub keys: Keys,
+    /// Sequence number of the last applied input.
+    pub ack: u32,
+    /// Inputs waiting to be applied, one per tick.
+    pub inputs: VecDeque<Input>,
+}
+impl Player {
+    /// Move by held keys for one tick.
+    pub fn advance(&mut self, keys: Keys) {
+        let axis = |neg, pos| keys.contains(pos) as i32 - keys.contains(neg) as i32;
+        let (dx, dy) = (axis(Keys::LEFT, Keys::RIGHT), axis(Keys::UP, Keys::DOWN));
+        self.x = self.x.saturating_add(dx * SPEED);
+        self.y = self.y.saturating_add(dy * SPEED);
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct World {
+    pub tick: u32,
+    pub players: BTreeMap<u32, Player>,
+}
+impl World {
+    pub fn join(&mut self, id: u32) {
+        self.players.insert(
+            id,
+            Player {
+                x: (id % 8) as i32 * 32,
+                ..Default::default()
+            },
+        );
+    }
+
+    pub fn leave(&mut self, id: u32) {
+        self.players.remove(&id);
+    }
+
+    /// Queue player input. Inputs older than already queued ones are ignored.
+    pub fn input(&mut self, id: u32, input: Input) {
+        let Some(x) = self.players.get_mut(&id) else {
+            return;
+        };
+        if input.seq <= x.inputs.back().map_or(x.ack, |x| x.seq) {
+            return;
+        }
+        if x.inputs.len() >= MAX_QUEUED_INPUTS {
+            x.inputs.pop_front();
+        }
+        x.inputs.push_back(input);
+    }
+
+    /// Advance the world by one tick. Players without queued input keep their last keys.
+    pub fn step(&mut self) {
+        for x in self.players.values_mut() {
+            if let Some(input) = x.inputs.pop_front() {
+                x.keys = input.keys;
+                x.ack = input.seq;
+            }
+            x.advance(x.keys);
+        }
+        self.tick = self.tick.wrapping_add(1);
+    }
+
+    pub fn entities(&self) -> Vec<Entity> {
+        self.players
+            .iter()
+            .map(|(id, x)| Entity {
+                id: *id,
+                x: x.x,
+                y: x.y,
+            })
+            .collect()
+    }
+
+    fn ack(&self, id: u32) -> u32 {
+        self.players.get(&id).map_or(0, |x| x.ack)
+    }
+
+    /// Full state as seen by player `id`.
+    pub fn snapshot(&self, id: u32) -> Snapshot {
+        Snapshot {
+            tick: self.tick,
+            ack: self.ack(id),
+            entities: self.entities(),
+        }
+    }
+
+    /// State as seen by player `id`, relative to `base` entities at tick `base_tick`.
+    pub fn delta(&self, base_tick: u32, base: &[Entity], id: u32) -> Delta {
+        let entities = self.entities();
+        Delta {
+            tick: self.tick,
+            base: base_tick,
+            ack: self.ack(id),
+            changed: entities
+                .iter()
+                .filter(|x| !base.contains(x))
+                .copied()
+                .collect(),
+            removed: base
+                .iter()
+                .filter(|x| !self.players.contains_key(&x.id))
+                .map(|x| x.id)
+                .collect(),
+        }
+    }
+
+    /// Replace the world with state received from the server.
+    pub fn apply_snapshot(&mut self, snapshot: &Snapshot) {
+        self.tick = snapshot.tick;
+        self.players.clear();
+        for x in &snapshot.entities {
+            let player = self.players.entry(x.id).or_default();
+            (player.x, player.y) = (x.x, x.y);
+        }
+    }
+
+    /// Apply a delta received from the server. Returns `false` if it's for another tick.
+    pub fn apply_delta(&mut self, delta: &Delta) -> bool {
+        if delta.base != self.tick {
+            return false;
+        }
+        self.tick = delta.tick;
+        for x in &delta.removed {
+            self.players.remove(x);
+        }
+        for x in &delta.changed {
+            let player = self.players.entry(x.id).or_default();
+            (player.x, player.y) = (x.x, x.y);
+        }
+        true
+    }
+}
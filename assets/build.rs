@@ -6,6 +6,10 @@ use std::{
     path::Path,
 };
 
+mod fluent;
+mod plural;
+mod voidsn;
+
 static ABS: &[u8] = b"$crate::Abstract";
 
 struct Writers {
@@ -14,6 +18,41 @@ struct Writers {
     pub resources: Cursor<Vec<u8>>,
     pub resource_methods: Cursor<Vec<u8>>,
     pub overrides: Cursor<Vec<u8>>,
+    pub paths: Cursor<Vec<u8>>,
+    pub manifest: Vec<(String, String)>,
+}
+
+/// `,gzip:..,br:..` fields of `Abstract`, with compressed files written into `$OUT_DIR/compressed`.
+///
+/// A variant is `None` if it isn't smaller than the original.
+#[cfg(feature = "compressed")]
+fn compressed(bytes: &[u8], ident: &str) -> String {
+    let dir = Path::new(&env::var_os("OUT_DIR").unwrap()).join("compressed");
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut fields = String::new();
+    for (name, (ext, x)) in ["gzip", "br"]
+        .into_iter()
+        .zip(build_support::compress(bytes))
+    {
+        match x {
+            Some(x) => {
+                let dest = dir.join(format!("{ident}.{ext}"));
+                fs::write(&dest, x).unwrap();
+                fields.push_str(&format!(
+                    ",{name}:Some(include_bytes!({:?}))",
+                    dest.to_string_lossy()
+                ));
+            }
+            None => fields.push_str(&format!(",{name}:None")),
+        }
+    }
+    fields
+}
+
+#[cfg(not(feature = "compressed"))]
+fn compressed(_: &[u8], _: &str) -> String {
+    String::new()
 }
 
 #[derive(Debug)]
@@ -84,8 +123,7 @@ impl<'a> Prefix<'a> {
         }
     }
 
-    #[allow(unused)]
-    fn parent(&self) -> &Prefix {
+    fn parent(&self) -> &Prefix<'_> {
         match self {
             Prefix::Empty | Prefix::Source(_) => &Prefix::Empty,
             Prefix::Extended(x, _) => x,
@@ -97,7 +135,7 @@ impl<'a> Prefix<'a> {
         matches!(self, Prefix::Empty)
     }
 
-    fn parts(&self) -> PrefixIter {
+    fn parts(&self) -> PrefixIter<'_> {
         PrefixIter::Prepare(self)
     }
 }
@@ -115,7 +153,8 @@ fn walk(path: &Path, file: &mut Writers, prefix: Prefix) {
         }
         Err(why) if why.kind() == ErrorKind::NotADirectory => {
             macro_rules! walk_file_ty {
-                ($macro_ty:ident, $mime:expr, $ty:ident) => {{
+                ($macro_ty:ident, $mime:expr, $ty:ident, $variant:ident, $source:expr, $prefix:expr) => {{
+                    let prefix = $prefix;
                     let ident = {
                         let mut string = String::new();
                         for (i, x) in prefix.parts().enumerate() {
@@ -137,16 +176,6 @@ fn walk(path: &Path, file: &mut Writers, prefix: Prefix) {
                     file.includes.write_all(b"={\n\tlet $").unwrap();
                     file.includes.write_all(format).unwrap();
                     file.includes.write_all(b"=").unwrap();
-                    file.includes.write_all(ABS).unwrap();
-                    file.includes
-                        .write_all(
-                            format!(
-                                "{{bytes:include_bytes!({:?}),path:\"",
-                                path.to_string_lossy()
-                            )
-                            .as_bytes(),
-                        )
-                        .unwrap();
 
                     let rel_path = {
                         let mut string = String::new();
@@ -157,12 +186,24 @@ fn walk(path: &Path, file: &mut Writers, prefix: Prefix) {
                         string
                     };
 
-                    file.includes.write_all(rel_path.as_bytes()).unwrap();
-                    file.includes.write_all(b"\",mime:\"").unwrap();
-                    file.includes.write_all($mime.as_bytes()).unwrap();
-                    file.includes.write_all(b"\"};\n\t$").unwrap();
+                    // Abstract { bytes: include_bytes!(".."), path: "/..", mime: "..", hash: "..", <compressed> }
+
+                    let bytes = fs::read(&$source).unwrap();
+                    let fields = format!(
+                        "{{bytes:include_bytes!({:?}),path:{:?},mime:{:?},hash:{:?}{}}}",
+                        $source.to_string_lossy(),
+                        rel_path,
+                        $mime,
+                        build_support::hash(&bytes),
+                        compressed(&bytes, &ident),
+                    );
+
+                    file.includes.write_all(ABS).unwrap();
+                    file.includes.write_all(fields.as_bytes()).unwrap();
+                    file.includes.write_all(b";\n\t$").unwrap();
                     file.includes.write_all(format).unwrap();
                     file.includes.write_all(b"_trans\n};").unwrap();
+                    file.manifest.push((rel_path.clone(), fields));
 
                     // let <ident> = {
                     //     let $<format>_ov = <ident>;
@@ -193,11 +234,46 @@ fn walk(path: &Path, file: &mut Writers, prefix: Prefix) {
                         .write_all(stringify!($ty).as_bytes())
                         .unwrap();
                     file.resources.write_all(b",").unwrap();
+
+                    // "<rel_path>" => Some(ResourceMut::<variant>(&mut self.<ident>)),
+
+                    file.paths
+                        .write_all(format!("{rel_path:?}=>Some(ResourceMut::").as_bytes())
+                        .unwrap();
+                    file.paths
+                        .write_all(stringify!($variant).as_bytes())
+                        .unwrap();
+                    file.paths.write_all(b"(&mut self.").unwrap();
+                    file.paths.write_all(ident.as_bytes()).unwrap();
+                    file.paths.write_all(b")),\n").unwrap();
                 }};
             }
             match path.file_name().unwrap().to_string_lossy().split_once('.') {
-                Some((_, "png")) => walk_file_ty!(png, "image/png", Tex),
-                Some((_, "ttf")) => walk_file_ty!(ttf, "font/ttf", Font),
+                Some((_, "png")) => walk_file_ty!(png, "image/png", Tex, Png, path, &prefix),
+                Some((_, "ttf")) => walk_file_ty!(ttf, "font/ttf", Font, Ttf, path, &prefix),
+                Some((_, "toml")) => {
+                    walk_file_ty!(data, "application/toml", Data, Data, path, &prefix)
+                }
+                Some((_, "json")) => {
+                    walk_file_ty!(data, "application/json", Data, Data, path, &prefix)
+                }
+                Some((_, "ron")) => {
+                    walk_file_ty!(data, "application/ron", Data, Data, path, &prefix)
+                }
+                Some((name, "voidsn")) => {
+                    // Flattened into `$OUT_DIR/voidsn/<path>.png` and included as a png
+                    let png = format!("{name}.png");
+                    let prefix = prefix.parent().extend(&png);
+                    let dest = prefix.parts().fold(
+                        Path::new(&env::var_os("OUT_DIR").unwrap()).join("voidsn"),
+                        |x, y| x.join(y),
+                    );
+                    let bytes = voidsn::to_png(&fs::read(path).unwrap())
+                        .unwrap_or_else(|why| panic!("'{}': {why}", path.display()));
+                    fs::create_dir_all(dest.parent().unwrap()).unwrap();
+                    fs::write(&dest, bytes).unwrap();
+                    walk_file_ty!(png, "image/png", Tex, Png, dest, &prefix)
+                }
                 Some((_, "sprites.csv")) => {
                     for x in BufReader::new(File::open(path).unwrap()).lines() {
                         let x = x.unwrap();
@@ -227,7 +303,7 @@ fn walk(path: &Path, file: &mut Writers, prefix: Prefix) {
                         file.resource_methods.write_all(b"pub fn ").unwrap();
                         file.resource_methods.write_all(ident.as_bytes()).unwrap();
                         file.resource_methods
-                            .write_all(b"(&self)->Sprite<Tex>{\nSprite{tex:&self.")
+                            .write_all(b"(&self)->Sprite<'_,Tex>{\nSprite{tex:&self.")
                             .unwrap();
                         file.resource_methods.write_all(tex.as_bytes()).unwrap();
                         file.resource_methods.write_all(b",rect:(").unwrap();
@@ -263,6 +339,8 @@ fn main() {
         resources: Cursor::new(vec![]),
         resource_methods: Cursor::new(vec![]),
         overrides: Cursor::new(vec![]),
+        paths: Cursor::new(vec![]),
+        manifest: vec![],
     };
 
     writers
@@ -274,17 +352,41 @@ fn main() {
 
     writers
         .includes
-        .write_all("pub struct Abstract{pub bytes:&'static[u8],pub path:&'static str,pub mime:&'static str}".as_bytes())
+        .write_all("#[derive(Clone,Copy)]pub struct Abstract{pub bytes:&'static[u8],pub path:&'static str,pub mime:&'static str,pub hash:&'static str,".as_bytes())
+        .unwrap();
+    #[cfg(feature = "compressed")]
+    writers
+        .includes
+        .write_all("pub gzip:Option<&'static[u8]>,pub br:Option<&'static[u8]>,".as_bytes())
         .unwrap();
+    writers.includes.write_all("}".as_bytes()).unwrap();
 
     writers
         .includes
-        .write_all("#[macro_export]macro_rules!include_resources{($png:ident .png => $png_trans:expr,$ttf:ident .ttf => $ttf_trans:expr,".as_bytes())
+        .write_all(
+            "pub enum ResourceMut<'a,Tex,Font,Data>{Png(&'a mut Tex),Ttf(&'a mut Font),Data(&'a mut Data)}".as_bytes(),
+        )
         .unwrap();
+
     writers
         .includes
         .write_all(
-            "$(+{$png_ov:ident .png=>$png_ov_trans:expr,$ttf_ov:ident .ttf=>$ttf_ov_trans:expr,})*)=>{{"
+            format!(
+                "pub const SOURCE_DIR:&str={:?};",
+                current_dir().unwrap().join("src").to_string_lossy()
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+    writers
+        .includes
+        .write_all("#[macro_export]macro_rules!include_resources{($png:ident .png => $png_trans:expr,$ttf:ident .ttf => $ttf_trans:expr,$data:ident .data => $data_trans:expr,".as_bytes())
+        .unwrap();
+    writers
+        .includes
+        .write_all(
+            "$(+{$png_ov:ident .png=>$png_ov_trans:expr,$ttf_ov:ident .ttf=>$ttf_ov_trans:expr,$data_ov:ident .data=>$data_ov_trans:expr,})*)=>{{"
                 .as_bytes(),
         )
         .unwrap();
@@ -305,14 +407,37 @@ fn main() {
     file.write_all(writers.abstracts.get_ref()).unwrap();
     file.write_all("}}}}".as_bytes()).unwrap();
 
-    file.write_all("pub struct Resources<Tex,Font>{".as_bytes())
+    file.write_all("pub struct Resources<Tex,Font,Data>{".as_bytes())
         .unwrap();
     file.write_all(writers.resources.get_ref()).unwrap();
     file.write_all("}".as_bytes()).unwrap();
-    file.write_all("impl<Tex,Font>Resources<Tex,Font>{".as_bytes())
+    file.write_all("impl<Tex,Font,Data>Resources<Tex,Font,Data>{".as_bytes())
         .unwrap();
     file.write_all(writers.resource_methods.get_ref()).unwrap();
-    file.write_all("}".as_bytes()).unwrap();
+    file.write_all(
+        "pub fn by_path_mut(&mut self,path:&str)->Option<ResourceMut<'_,Tex,Font,Data>>{match path{"
+            .as_bytes(),
+    )
+    .unwrap();
+    file.write_all(writers.paths.get_ref()).unwrap();
+    file.write_all("_=>None}}}".as_bytes()).unwrap();
+
+    // pub static MANIFEST: &[Abstract] = &[Abstract { .. }, ..]; (sorted by path)
+
+    writers.manifest.sort();
+    file.write_all("pub static MANIFEST:&[Abstract]=&[".as_bytes())
+        .unwrap();
+    for (_, x) in writers.manifest {
+        file.write_all("Abstract".as_bytes()).unwrap();
+        file.write_all(x.as_bytes()).unwrap();
+        file.write_all(",".as_bytes()).unwrap();
+    }
+    file.write_all("];".as_bytes()).unwrap();
+
+    fluent::compile(
+        &current_dir().unwrap().join("src/lang"),
+        &Path::new(&out_dir).join("lang.rs"),
+    );
 
     println!("cargo::rerun-if-changed=src/");
 }
@@ -0,0 +1,672 @@
+//! Build-time compiler for the subset of Fluent used in `src/lang`.
+//!
+//! Supported are messages, terms (`-name = ..`), comments, multiline values and placeables
+//! referencing variables (`{ $var }`), terms (`{ -term }`) and string literals (`{ "{" }`), as
+//! well as selectors on numbers picking exact values or plural categories, one line per variant:
+//!
+//! ```ftl
+//! files = { $count ->
+//!     [0] No files
+//!     [one] { $count } file
+//!    *[other] { $count } files
+//! }
+//! ```
+//!
+//! Anything else (attributes, functions, message references, nested selectors) is rejected, as
+//! are ids that would clash in the generated code.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::plural;
+
+/// The locale every other locale falls back to.
+pub const FALLBACK: &str = "en";
+
+const CATEGORIES: &[&str] = &["zero", "one", "two", "few", "many", "other"];
+
+/// Names the generated `Locale` already has, as its own items or through its derives.
+const RESERVED: &[&str] = &[
+    "ALL",
+    "code",
+    "from_code",
+    "clone",
+    "clone_from",
+    "default",
+    "eq",
+    "ne",
+    "fmt",
+    "hash",
+    "hash_slice",
+];
+
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl",
+    "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "true", "try", "type",
+    "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+#[derive(PartialEq)]
+enum Key {
+    Number(u64),
+    Category(String),
+}
+
+struct Variant {
+    line: usize,
+    key: Key,
+    default: bool,
+    parts: Vec<Part>,
+}
+
+enum Part {
+    Text(String),
+    Var(String),
+    Select { var: String, variants: Vec<Variant> },
+}
+
+/// Variables in `parts` and whether they're selected on, in order of appearance.
+fn vars<'a>(parts: &'a [Part], out: &mut Vec<(&'a str, bool)>) {
+    for x in parts {
+        let (name, selector) = match x {
+            Part::Text(_) => continue,
+            Part::Var(x) => (x, false),
+            Part::Select { var, variants } => {
+                for x in variants {
+                    vars(&x.parts, out);
+                }
+                (var, true)
+            }
+        };
+        match out.iter_mut().find(|x| x.0 == name) {
+            Some(x) => x.1 |= selector,
+            None => out.push((name, selector)),
+        }
+    }
+}
+
+struct Message {
+    line: usize,
+    parts: Vec<Part>,
+}
+impl Message {
+    fn vars(&self) -> Vec<(&str, bool)> {
+        let mut out = vec![];
+        vars(&self.parts, &mut out);
+        out
+    }
+}
+
+struct Locale {
+    code: String,
+    file: PathBuf,
+    messages: BTreeMap<String, Message>,
+}
+impl Locale {
+    /// Language without the region, for plural rules.
+    fn lang(&self) -> String {
+        self.code
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+    }
+}
+
+fn is_ident(x: &str) -> bool {
+    let mut chars = x.chars();
+    chars.next().is_some_and(|x| x.is_ascii_alphabetic())
+        && chars.all(|x| x.is_ascii_alphanumeric() || x == '-' || x == '_')
+}
+
+/// Name of a message or variable in the generated code.
+fn rust_name(x: &str) -> String {
+    x.replace('-', "_")
+}
+
+struct Parser<'a> {
+    file: &'a Path,
+    line: usize,
+    rest: &'a str,
+    terms: &'a BTreeMap<String, String>,
+}
+impl<'a> Parser<'a> {
+    fn fail(&self, why: &str) -> ! {
+        panic!("{}:{}: {why}", self.file.display(), self.line)
+    }
+
+    fn take(&mut self, len: usize) -> &'a str {
+        let (x, rest) = self.rest.split_at(len);
+        self.line += x.matches('\n').count();
+        self.rest = rest;
+        x
+    }
+
+    fn skip_blank(&mut self) {
+        let len = self.rest.len() - self.rest.trim_start().len();
+        self.take(len);
+    }
+
+    fn ident(&mut self, what: &str) -> String {
+        let len = self
+            .rest
+            .find(|x: char| !x.is_ascii_alphanumeric() && x != '-' && x != '_')
+            .unwrap_or(self.rest.len());
+        let x = self.take(len);
+        if !is_ident(x) {
+            self.fail(&format!("invalid {what} name '{x}'"));
+        }
+        x.to_string()
+    }
+
+    /// Text and placeables up to the end of the value, or of the line and `}` in a variant.
+    fn pattern(&mut self, variant: bool) -> Vec<Part> {
+        let mut parts = vec![];
+        let mut text = String::new();
+        loop {
+            let end = match variant {
+                true => self.rest.find(['{', '}', '\n']),
+                false => self.rest.find(['{', '}']),
+            };
+            let Some(end) = end else {
+                if variant {
+                    self.fail("unterminated selector");
+                }
+                text.push_str(self.take(self.rest.len()));
+                break;
+            };
+            text.push_str(self.take(end));
+            if self.rest.starts_with('{') {
+                self.take(1);
+                self.placeable(&mut parts, &mut text, variant);
+            } else if !variant {
+                self.fail("unbalanced '}'");
+            } else {
+                // The end of the variant, left for the selector.
+                break;
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        parts
+    }
+
+    /// The rest of a placeable after its `{`.
+    fn placeable(&mut self, parts: &mut Vec<Part>, text: &mut String, variant: bool) {
+        self.skip_blank();
+        let mut flush = |parts: &mut Vec<Part>| {
+            if !text.is_empty() {
+                parts.push(Part::Text(std::mem::take(text)));
+            }
+        };
+        if self.rest.starts_with('"') {
+            let literal = self.string();
+            text.push_str(&literal);
+        } else if self.rest.starts_with('$') {
+            self.take(1);
+            let var = self.ident("variable");
+            self.skip_blank();
+            flush(parts);
+            if self.rest.starts_with("->") {
+                if variant {
+                    self.fail("nested selectors are not supported");
+                }
+                self.take(2);
+                parts.push(self.select(var));
+                return;
+            }
+            parts.push(Part::Var(var));
+        } else if self.rest.starts_with('-') {
+            self.take(1);
+            let term = self.ident("term");
+            let value = self.terms.get(&term);
+            text.push_str(value.unwrap_or_else(|| self.fail(&format!("unknown term '-{term}'"))));
+        } else {
+            let inner = self.rest[..self.rest.find('}').unwrap_or(self.rest.len())].trim();
+            self.fail(&format!(
+                "unsupported placeable '{{ {inner} }}', only variables, terms, string literals \
+                 and selectors are supported"
+            ));
+        }
+        self.skip_blank();
+        if !self.rest.starts_with('}') {
+            self.fail("expected '}'");
+        }
+        self.take(1);
+    }
+
+    /// A string literal, with its escapes resolved.
+    fn string(&mut self) -> String {
+        let literal = &self.rest[1..];
+        let mut out = String::new();
+        let mut chars = literal.char_indices();
+        loop {
+            match chars.next() {
+                None | Some((_, '\n')) => self.fail("unterminated string"),
+                Some((i, '"')) => {
+                    self.take(i + 2);
+                    return out;
+                }
+                Some((i, '\\')) => match chars.next() {
+                    Some((_, x @ ('\\' | '"'))) => out.push(x),
+                    Some((_, x @ ('u' | 'U'))) => {
+                        let len = if x == 'u' { 4 } else { 6 };
+                        let hex: String = literal[i + 2..]
+                            .chars()
+                            .take(len)
+                            .take_while(char::is_ascii_hexdigit)
+                            .collect();
+                        for _ in 0..hex.len() {
+                            chars.next();
+                        }
+                        let c = Some(&hex)
+                            .filter(|x| x.len() == len)
+                            .and_then(|x| char::from_u32(u32::from_str_radix(x, 16).ok()?));
+                        out.push(
+                            c.unwrap_or_else(|| self.fail(&format!("invalid escape '\\{x}{hex}'"))),
+                        );
+                    }
+                    _ => self.fail(r#"unknown escape, only \\, \", \uXXXX and \UXXXXXX exist"#),
+                },
+                Some((_, x)) => out.push(x),
+            }
+        }
+    }
+
+    /// Variants of a selector on `$var`, up to and including its `}`.
+    fn select(&mut self, var: String) -> Part {
+        let mut variants: Vec<Variant> = vec![];
+        loop {
+            self.skip_blank();
+            if self.rest.starts_with('}') {
+                self.take(1);
+                break;
+            }
+            let line = self.line;
+            let default = self.rest.starts_with('*');
+            if default {
+                self.take(1);
+            }
+            if !self.rest.starts_with('[') {
+                self.fail("expected a variant like '[one]' or '*[other]'");
+            }
+            let Some(end) = self.rest.find(']') else {
+                self.fail("unterminated variant key");
+            };
+            let key = self.take(end + 1)[1..end].trim();
+            let key = match key.parse() {
+                Ok(x) => Key::Number(x),
+                Err(_) if CATEGORIES.contains(&key) => Key::Category(key.to_string()),
+                Err(_) => self.fail(&format!(
+                    "unsupported variant key '{key}', only whole numbers and plural categories \
+                     ({}) are supported",
+                    CATEGORIES.join(", ")
+                )),
+            };
+            if variants.iter().any(|x| x.key == key) {
+                self.fail("duplicate variant");
+            }
+            let blank = self.rest.len() - self.rest.trim_start_matches([' ', '\t']).len();
+            self.take(blank);
+            let mut parts = self.pattern(true);
+            if let Some(Part::Text(x)) = parts.last_mut() {
+                x.truncate(x.trim_end().len());
+            }
+            variants.push(Variant {
+                line,
+                key,
+                default,
+                parts,
+            });
+        }
+        if variants.iter().filter(|x| x.default).count() != 1 {
+            self.fail("selectors need exactly one default variant like '*[other]'");
+        }
+        Part::Select { var, variants }
+    }
+}
+
+fn parse_value(
+    file: &Path,
+    line: usize,
+    value: &str,
+    terms: &BTreeMap<String, String>,
+) -> Vec<Part> {
+    let mut parser = Parser {
+        file,
+        line,
+        rest: value,
+        terms,
+    };
+    parser.pattern(false)
+}
+
+fn parse(file: &Path, source: &str) -> BTreeMap<String, Message> {
+    // Entries as (line, id, value) with continuation lines joined
+    let mut entries: Vec<(usize, String, String)> = vec![];
+    for (i, x) in source.lines().enumerate() {
+        let line = i + 1;
+        // Lines inside a placeable don't need indentation, the ones of selectors start so.
+        if x.starts_with([' ', '\t', '[', '*', '}']) {
+            match entries.last_mut() {
+                Some((_, _, value)) if !x.trim().is_empty() => {
+                    if x.trim_start().starts_with('.') {
+                        panic!("{}:{line}: attributes are not supported", file.display());
+                    }
+                    if !value.is_empty() {
+                        value.push('\n');
+                    }
+                    value.push_str(x.trim());
+                }
+                Some(_) => (),
+                None => panic!("{}:{line}: unexpected indentation", file.display()),
+            }
+        } else if x.starts_with('#') || x.trim().is_empty() {
+            continue;
+        } else {
+            let (id, value) = x
+                .split_once('=')
+                .unwrap_or_else(|| panic!("{}:{line}: expected '='", file.display()));
+            let id = id.trim();
+            if !is_ident(id.strip_prefix('-').unwrap_or(id)) {
+                panic!("{}:{line}: invalid identifier '{id}'", file.display());
+            }
+            entries.push((line, id.to_string(), value.trim().to_string()));
+        }
+    }
+
+    // Terms first, since messages may reference terms defined after them
+    let mut terms = BTreeMap::new();
+    for (line, id, value) in &entries {
+        let Some(id) = id.strip_prefix('-') else {
+            continue;
+        };
+        let mut text = String::new();
+        for x in parse_value(file, *line, value, &terms) {
+            match x {
+                Part::Text(x) => text.push_str(&x),
+                _ => panic!("{}:{line}: terms can't use variables", file.display()),
+            }
+        }
+        if terms.insert(id.to_string(), text).is_some() {
+            panic!("{}:{line}: '-{id}' is defined twice", file.display());
+        }
+    }
+
+    let mut messages = BTreeMap::new();
+    for (line, id, value) in entries {
+        if id.starts_with('-') {
+            continue;
+        }
+        let parts = parse_value(file, line, &value, &terms);
+        if messages
+            .insert(id.clone(), Message { line, parts })
+            .is_some()
+        {
+            panic!("{}:{line}: '{id}' is defined twice", file.display());
+        }
+    }
+    messages
+}
+
+/// `en` -> `En`, `pt-BR` -> `PtBr`
+fn variant(code: &str) -> String {
+    let mut string = String::new();
+    for x in code.split(['-', '_']) {
+        let mut chars = x.chars();
+        if let Some(x) = chars.next() {
+            string.push(x.to_ascii_uppercase());
+            string.extend(chars.map(|x| x.to_ascii_lowercase()));
+        }
+    }
+    string
+}
+
+/// Plain text of `parts`, which have no variables.
+fn text(parts: &[Part]) -> String {
+    let mut string = String::new();
+    for x in parts {
+        if let Part::Text(x) = x {
+            string.push_str(x);
+        }
+    }
+    string
+}
+
+/// `parts` as an expression building a `String`, variables captured by name.
+fn expr(parts: &[Part], lang: &str) -> String {
+    if let [Part::Select { var, variants }] = parts {
+        return select(var, variants, lang);
+    }
+    let mut format = String::new();
+    let mut args = String::new();
+    for x in parts {
+        match x {
+            Part::Text(x) => format.push_str(&x.replace('{', "{{").replace('}', "}}")),
+            Part::Var(x) => write!(format, "{{{}}}", rust_name(x)).unwrap(),
+            Part::Select { var, variants } => {
+                format.push_str("{}");
+                write!(args, ",{}", select(var, variants, lang)).unwrap();
+            }
+        }
+    }
+    let mut vars = vec![];
+    self::vars(parts, &mut vars);
+    match vars.is_empty() {
+        true => format!("String::from({:?})", text(parts)),
+        false => format!("format!({format:?}{args})"),
+    }
+}
+
+// match <var> { 0 => .., _ => match crate::plural::category("<lang>", <var>) { "one" => .., _ => .. } }
+fn select(var: &str, variants: &[Variant], lang: &str) -> String {
+    let var = rust_name(var);
+    let mut numbers = String::new();
+    let mut categories = String::new();
+    for x in variants.iter().filter(|x| !x.default) {
+        match &x.key {
+            Key::Number(n) => write!(numbers, "{n}=>{},", expr(&x.parts, lang)).unwrap(),
+            Key::Category(c) => write!(categories, "{c:?}=>{},", expr(&x.parts, lang)).unwrap(),
+        }
+    }
+    let default = variants.iter().find(|x| x.default).unwrap();
+    let mut string = expr(&default.parts, lang);
+    if !categories.is_empty() {
+        string =
+            format!("match crate::plural::category({lang:?},{var}){{{categories}_=>{string}}}");
+    }
+    if !numbers.is_empty() {
+        string = format!("match {var}{{{numbers}_=>{string}}}");
+    }
+    string
+}
+
+/// Check that `name` can be a parameter or method name in the generated code.
+fn check_name(file: &Path, line: usize, what: &str, name: &str, reserved: &[&str]) {
+    let rust = rust_name(name);
+    if KEYWORDS.contains(&rust.as_str()) || reserved.contains(&rust.as_str()) {
+        panic!(
+            "{}:{line}: {what} '{name}' clashes with the generated code, rename it",
+            file.display()
+        );
+    }
+}
+
+/// Check that the plural categories `parts` select on exist in `lang`.
+fn check_categories(file: &Path, lang: &str, parts: &[Part]) {
+    for x in parts {
+        let Part::Select { variants, .. } = x else {
+            continue;
+        };
+        for x in variants.iter().filter(|x| !x.default) {
+            if let Key::Category(c) = &x.key {
+                if !(0..1000).any(|n| plural::category(lang, n) == c) {
+                    panic!(
+                        "{}:{}: '[{c}]' is never selected in '{lang}', see plural.rs",
+                        file.display(),
+                        x.line
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Generate the `Locale` enum from `<locale>.ftl` files and their contents.
+pub fn generate(files: &[(PathBuf, String)]) -> String {
+    let mut locales = vec![];
+    for (file, source) in files {
+        let name = file.file_name().unwrap().to_string_lossy();
+        if let Some(code) = name.strip_suffix(".ftl") {
+            locales.push(Locale {
+                code: code.to_string(),
+                file: file.clone(),
+                messages: parse(file, source),
+            });
+        }
+    }
+    locales.sort_by(|a, b| (a.code != FALLBACK, &a.code).cmp(&(b.code != FALLBACK, &b.code)));
+    let Some(fallback) = locales.first().filter(|x| x.code == FALLBACK) else {
+        panic!("there's no {FALLBACK}.ftl");
+    };
+
+    let mut variants = BTreeMap::new();
+    for x in &locales {
+        if let Some(other) = variants.insert(variant(&x.code), &x.code) {
+            panic!("locales '{other}' and '{}' have the same name", x.code);
+        }
+    }
+    let mut names = BTreeMap::new();
+    for (id, message) in &fallback.messages {
+        let file = &fallback.file;
+        check_name(file, message.line, "message", id, RESERVED);
+        if let Some(other) = names.insert(rust_name(id), id) {
+            panic!(
+                "{}:{}: '{other}' and '{id}' would have the same name",
+                file.display(),
+                message.line
+            );
+        }
+    }
+
+    // Translations may only use messages and variables the fallback has.
+    for locale in &locales[1..] {
+        for (id, message) in &locale.messages {
+            let file = &locale.file;
+            let Some(original) = fallback.messages.get(id) else {
+                panic!("{}: '{id}' is not in {FALLBACK}.ftl", file.display());
+            };
+            for (x, _) in message.vars() {
+                if !original.vars().iter().any(|y| y.0 == x) {
+                    panic!("{}: '{id}' uses unknown variable '${x}'", file.display());
+                }
+            }
+        }
+    }
+    for locale in &locales {
+        for message in locale.messages.values() {
+            check_categories(&locale.file, &locale.lang(), &message.parts);
+        }
+    }
+
+    let mut out = String::new();
+
+    // pub enum Locale { En, .. }
+
+    out.push_str("#[derive(Debug,Default,PartialEq,Eq,Hash,Clone,Copy)]pub enum Locale{");
+    for (i, x) in locales.iter().enumerate() {
+        if i == 0 {
+            out.push_str("#[default]");
+        }
+        write!(out, "{},", variant(&x.code)).unwrap();
+    }
+    out.push_str("}impl Locale{pub const ALL:&[Locale]=&[");
+    for x in &locales {
+        write!(out, "Locale::{},", variant(&x.code)).unwrap();
+    }
+
+    // pub fn code(self) -> &'static str { match self { Locale::En => "en", .. } }
+
+    out.push_str("];pub fn code(self)->&'static str{match self{");
+    for x in &locales {
+        write!(out, "Locale::{}=>{:?},", variant(&x.code), x.code).unwrap();
+    }
+    out.push_str("}}");
+
+    // pub fn <id>(self, <var>: impl Display, <selector>: u64, ..) -> String {
+    //     match self { Locale::Ru => format!("..{<var>}.."), _ => format!("..{<var>}..") }
+    // }
+
+    for (id, original) in &fallback.messages {
+        let translations: Vec<_> = locales[1..]
+            .iter()
+            .filter_map(|x| Some((x, x.messages.get(id)?)))
+            .collect();
+        // Selected on in any locale makes a variable a number in all of them.
+        let mut vars = original.vars();
+        for (_, x) in &translations {
+            for (name, selector) in x.vars() {
+                if selector {
+                    vars.iter_mut().find(|x| x.0 == name).unwrap().1 = true;
+                }
+            }
+        }
+        let mut params = BTreeMap::new();
+        write!(out, "pub fn {}(self", rust_name(id)).unwrap();
+        for (x, selector) in &vars {
+            let file = &fallback.file;
+            check_name(file, original.line, "variable", x, &[]);
+            if let Some(other) = params.insert(rust_name(x), x) {
+                panic!(
+                    "{}:{}: '${other}' and '${x}' would have the same name",
+                    file.display(),
+                    original.line
+                );
+            }
+            match selector {
+                true => write!(out, ",{}:u64", rust_name(x)).unwrap(),
+                false => write!(out, ",{}:impl core::fmt::Display", rust_name(x)).unwrap(),
+            }
+        }
+        out.push_str(match vars.is_empty() {
+            true => ")->&'static str{",
+            false => ")->String{",
+        });
+        let value = |locale: &Locale, message: &Message| match vars.is_empty() {
+            true => format!("{:?}", text(&message.parts)),
+            false => expr(&message.parts, &locale.lang()),
+        };
+        if translations.is_empty() {
+            out.push_str(&value(fallback, original));
+        } else {
+            out.push_str("match self{");
+            for (locale, message) in translations {
+                let value = value(locale, message);
+                write!(out, "Locale::{}=>{value},", variant(&locale.code)).unwrap();
+            }
+            write!(out, "_=>{}}}", value(fallback, original)).unwrap();
+        }
+        out.push('}');
+    }
+    out.push('}');
+    out
+}
+
+/// Compile all `<locale>.ftl` files in `dir` into `dest`.
+pub fn compile(dir: &Path, dest: &Path) {
+    let mut files = vec![];
+    for x in fs::read_dir(dir).unwrap().map(|x| x.unwrap().path()) {
+        if x.extension().is_some_and(|x| x == "ftl") {
+            let source = fs::read_to_string(&x).unwrap();
+            files.push((x, source));
+        }
+    }
+    fs::write(dest, generate(&files)).unwrap();
+}
@@ -0,0 +1,33 @@
+//! CLDR cardinal plural rules for whole numbers, see
+//! <https://www.unicode.org/cldr/charts/latest/supplemental/language_plural_rules.html>.
+//!
+//! Shared between the build script, which checks that translations only use categories their
+//! language has, and the generated [`Locale`](crate::lang::Locale) methods.
+
+/// Plural category of `n` in language `lang` (`en`, `ru`, ..). Languages without rules here
+/// only have `other`.
+pub fn category(lang: &str, n: u64) -> &'static str {
+    let (n10, n100) = (n % 10, n % 100);
+    match lang {
+        "en" | "de" | "nl" | "sv" | "da" | "nb" | "fi" | "et" => match n {
+            1 => "one",
+            _ => "other",
+        },
+        "ru" | "uk" | "be" => match (n10, n100) {
+            (1, x) if x != 11 => "one",
+            (2..=4, x) if !(12..=14).contains(&x) => "few",
+            _ => "many",
+        },
+        "pl" => match (n, n10, n100) {
+            (1, ..) => "one",
+            (_, 2..=4, x) if !(12..=14).contains(&x) => "few",
+            _ => "many",
+        },
+        "cs" | "sk" => match n {
+            1 => "one",
+            2..=4 => "few",
+            _ => "other",
+        },
+        _ => "other",
+    }
+}
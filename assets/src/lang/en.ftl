@@ -0,0 +1,12 @@
+# Every message here becomes a method on `assets::lang::Locale`.
+# Other locales fall back to this file for missing messages.
+# Selectors on numbers pick plural forms by the rules in `assets/plural.rs`, see `fluent.rs`.
+
+hello = Hello!
+
+loading = Loading… { $percent }%
+
+load-failed = { $count ->
+    [one] Failed to load a file, reload the page to try again.
+   *[other] Failed to load { $count } files, reload the page to try again.
+}
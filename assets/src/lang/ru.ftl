@@ -0,0 +1,9 @@
+hello = Привет!
+
+loading = Загрузка… { $percent }%
+
+load-failed = { $count ->
+    [one] Не удалось загрузить { $count } файл, обновите страницу.
+    [few] Не удалось загрузить { $count } файла, обновите страницу.
+   *[many] Не удалось загрузить { $count } файлов, обновите страницу.
+}
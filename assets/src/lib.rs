@@ -1 +1,68 @@
 include!(concat!(env!("OUT_DIR"), "/lib.rs"));
+
+#[path = "../plural.rs"]
+pub mod plural;
+
+/// Translations from `src/lang`.
+///
+/// Every message is a method on [`Locale`](lang::Locale), falling back to English if the
+/// locale doesn't have it.
+pub mod lang {
+    include!(concat!(env!("OUT_DIR"), "/lang.rs"));
+
+    impl Locale {
+        /// Find a locale by language tag (`ru-RU`) or POSIX locale name (`ru_RU.UTF-8`).
+        ///
+        /// Region is ignored if there's no exact match.
+        pub fn from_code(code: &str) -> Option<Self> {
+            let code = code.split('.').next().unwrap_or_default().replace('_', "-");
+            let lang = code.split('-').next().unwrap_or_default();
+            Self::ALL
+                .iter()
+                .find(|x| x.code().eq_ignore_ascii_case(&code))
+                .or_else(|| {
+                    Self::ALL
+                        .iter()
+                        .find(|x| x.code().eq_ignore_ascii_case(lang))
+                })
+                .copied()
+        }
+    }
+}
+
+#[cfg(feature = "source")]
+#[path = "../voidsn.rs"]
+pub mod voidsn;
+
+#[cfg(feature = "source")]
+use std::{io, path::Path};
+
+/// Read a resource from [`SOURCE_DIR`] instead of the embedded copy.
+///
+/// Only works on the machine the crate was built on.
+#[cfg(feature = "source")]
+pub fn read_source(path: &str) -> io::Result<Vec<u8>> {
+    let file = format!("{SOURCE_DIR}{path}");
+    if let Some(name) = file.strip_suffix(".png") {
+        let project = format!("{name}.voidsn");
+        if Path::new(&project).exists() {
+            return voidsn::to_png(&std::fs::read(project)?).map_err(io::Error::other);
+        }
+    }
+    std::fs::read(file)
+}
+
+/// Get resource path of a file inside [`SOURCE_DIR`].
+#[cfg(feature = "source")]
+pub fn resource_path(file: &Path) -> Option<String> {
+    let path = file.strip_prefix(SOURCE_DIR).ok()?;
+    let mut string = String::new();
+    for x in path.components() {
+        string.push('/');
+        string.push_str(x.as_os_str().to_str()?);
+    }
+    if let Some(name) = string.strip_suffix(".voidsn") {
+        string = format!("{name}.png");
+    }
+    Some(string)
+}
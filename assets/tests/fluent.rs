@@ -0,0 +1,290 @@
+//! The Fluent compiler runs in the build script, so it's included directly.
+
+use std::path::PathBuf;
+
+// Only `generate` is tested, `compile` just reads and writes files around it.
+#[allow(dead_code)]
+#[path = "../fluent.rs"]
+mod fluent;
+#[path = "../plural.rs"]
+mod plural;
+
+fn generate(files: &[(&str, &str)]) -> String {
+    let files: Vec<_> = files
+        .iter()
+        .map(|(name, source)| (PathBuf::from(name), source.to_string()))
+        .collect();
+    fluent::generate(&files)
+}
+
+fn en(source: &str) -> String {
+    generate(&[("en.ftl", source)])
+}
+
+#[test]
+fn plain() {
+    let code = en("# Comment\nhello = Hello!\nlong = First\n    second\n");
+    assert!(code.contains(r#"pub fn hello(self)->&'static str{"Hello!"}"#));
+    assert!(code.contains(r#"pub fn long(self)->&'static str{"First\nsecond"}"#));
+}
+
+#[test]
+fn variables() {
+    let code = en("greet = Hi { $name }, {$the-time}! { $name }");
+    assert!(code.contains(
+        r#"pub fn greet(self,name:impl core::fmt::Display,the_time:impl core::fmt::Display)->String{format!("Hi {name}, {the_time}! {name}")}"#
+    ));
+}
+
+#[test]
+fn escapes() {
+    let code = en(r#"x = { "{" }{ "\"" }{ "\\" }{ "\u00e9" }{ "\U01F980" }"#);
+    assert!(code.contains(r#"pub fn x(self)->&'static str{"{\"\\é🦀"}"#));
+    let code = en(r#"y = { "{" }{ $a }{ "}" }"#);
+    assert!(code.contains(r#"format!("{{{a}}}")"#));
+}
+
+#[test]
+fn terms() {
+    let code = en("-brand = Usmg\ntitle = { -brand } { -brand }!");
+    assert!(code.contains(r#"pub fn title(self)->&'static str{"Usmg Usmg!"}"#));
+    assert!(!code.contains("brand("));
+}
+
+#[test]
+fn translations() {
+    let code = generate(&[
+        ("ru.ftl", "hello = Привет!\nloading = { $percent }%"),
+        (
+            "en.ftl",
+            "hello = Hello!\nloading = Loading { $percent }%\nbye = Bye",
+        ),
+    ]);
+    assert!(code.contains("pub enum Locale{#[default]En,Ru,}"));
+    assert!(code.contains(r#"Locale::Ru=>"ru""#));
+    assert!(code.contains(r#"match self{Locale::Ru=>"Привет!",_=>"Hello!"}"#));
+    assert!(code.contains(r#"Locale::Ru=>format!("{percent}%"),_=>format!("Loading {percent}%")"#));
+    assert!(code.contains(r#"pub fn bye(self)->&'static str{"Bye"}"#));
+}
+
+#[test]
+fn selectors() {
+    let code = generate(&[
+        (
+            "en.ftl",
+            "files = Got { $count ->\n    [0] none\n    [one] one file\n   *[other] { $count } files\n}",
+        ),
+        (
+            "ru.ftl",
+            "files = { $count ->\n [one] { $count } файл\n [few] { $count } файла\n*[many] { $count } файлов\n}",
+        ),
+    ]);
+    assert!(code.contains("pub fn files(self,count:u64)->String"));
+    assert!(code.contains(
+        r#"_=>format!("Got {}",match count{0=>String::from("none"),_=>match crate::plural::category("en",count){"one"=>String::from("one file"),_=>format!("{count} files")}})"#
+    ));
+    assert!(code.contains(
+        r#"Locale::Ru=>match crate::plural::category("ru",count){"one"=>format!("{count} файл"),"few"=>format!("{count} файла"),_=>format!("{count} файлов")}"#
+    ));
+}
+
+#[test]
+fn selected_in_translation() {
+    let code = generate(&[
+        ("en.ftl", "n = { $n } times"),
+        (
+            "ru.ftl",
+            "n = { $n ->\n    [one] { $n } раз\n   *[many] { $n } раз\n}",
+        ),
+    ]);
+    assert!(code.contains("pub fn n(self,n:u64)->String"));
+}
+
+#[test]
+#[should_panic(expected = "'a-b' and 'a_b' would have the same name")]
+fn colliding_messages() {
+    en("a-b = x\na_b = y");
+}
+
+#[test]
+#[should_panic(expected = "'$a-b' and '$a_b' would have the same name")]
+fn colliding_variables() {
+    en("x = { $a-b } { $a_b }");
+}
+
+#[test]
+#[should_panic(expected = "en.ftl:2: message 'code' clashes with the generated code")]
+fn reserved_message() {
+    en("hello = x\ncode = y");
+}
+
+#[test]
+#[should_panic(expected = "message 'ALL' clashes")]
+fn reserved_const() {
+    en("ALL = x");
+}
+
+#[test]
+#[should_panic(expected = "message 'type' clashes")]
+fn keyword_message() {
+    en("type = x");
+}
+
+#[test]
+#[should_panic(expected = "variable 'self' clashes")]
+fn keyword_variable() {
+    en("x = { $self }");
+}
+
+#[test]
+#[should_panic(expected = "locales 'pt-BR' and 'pt_BR' have the same name")]
+fn colliding_locales() {
+    generate(&[("en.ftl", ""), ("pt-BR.ftl", ""), ("pt_BR.ftl", "")]);
+}
+
+#[test]
+#[should_panic(expected = "en.ftl:2: 'x' is defined twice")]
+fn duplicate_message() {
+    en("x = a\nx = b");
+}
+
+#[test]
+#[should_panic(expected = "unsupported placeable '{ other }'")]
+fn message_reference() {
+    en("x = { other }");
+}
+
+#[test]
+#[should_panic(expected = "unsupported placeable '{ NUMBER($n) }'")]
+fn function() {
+    en("x = { NUMBER($n) }");
+}
+
+#[test]
+#[should_panic(expected = "en.ftl:2: attributes are not supported")]
+fn attribute() {
+    en("x = a\n    .title = b");
+}
+
+#[test]
+#[should_panic(expected = "nested selectors are not supported")]
+fn nested_selector() {
+    en("x = { $a ->\n *[other] { $b ->\n *[other] b\n }\n}");
+}
+
+#[test]
+#[should_panic(expected = "exactly one default variant")]
+fn missing_default() {
+    en("x = { $n ->\n    [one] a\n    [other] b\n}");
+}
+
+#[test]
+#[should_panic(expected = "exactly one default variant")]
+fn two_defaults() {
+    en("x = { $n ->\n   *[one] a\n   *[other] b\n}");
+}
+
+#[test]
+#[should_panic(expected = "duplicate variant")]
+fn duplicate_variant() {
+    en("x = { $n ->\n    [one] a\n    [one] b\n   *[other] c\n}");
+}
+
+#[test]
+#[should_panic(expected = "en.ftl:3: '[few]' is never selected in 'en'")]
+fn unused_category() {
+    en("x = { $n ->\n    [one] a\n    [few] b\n   *[other] c\n}");
+}
+
+#[test]
+#[should_panic(expected = "unsupported variant key 'lots'")]
+fn unknown_key() {
+    en("x = { $n ->\n    [lots] a\n   *[other] c\n}");
+}
+
+#[test]
+#[should_panic(expected = "unterminated selector")]
+fn unterminated_selector() {
+    en("x = { $n ->\n   *[other] c");
+}
+
+#[test]
+#[should_panic(expected = "terms can't use variables")]
+fn term_variable() {
+    en("-t = { $x }");
+}
+
+#[test]
+#[should_panic(expected = "unknown term '-t'")]
+fn unknown_term() {
+    en("x = { -t }");
+}
+
+#[test]
+#[should_panic(expected = "unknown escape")]
+fn unknown_escape() {
+    en(r#"x = { "\n" }"#);
+}
+
+#[test]
+#[should_panic(expected = r"invalid escape '\u12'")]
+fn short_escape() {
+    en(r#"x = { "\u12" }"#);
+}
+
+#[test]
+#[should_panic(expected = "unterminated string")]
+fn unterminated_string() {
+    en(r#"x = { "a }"#);
+}
+
+#[test]
+#[should_panic(expected = "unbalanced '}'")]
+fn unbalanced() {
+    en("x = a }");
+}
+
+#[test]
+#[should_panic(expected = "ru.ftl: 'x' uses unknown variable '$b'")]
+fn unknown_variable() {
+    generate(&[("en.ftl", "x = { $a }"), ("ru.ftl", "x = { $b }")]);
+}
+
+#[test]
+#[should_panic(expected = "ru.ftl: 'y' is not in en.ftl")]
+fn unknown_message() {
+    generate(&[("en.ftl", "x = a"), ("ru.ftl", "y = b")]);
+}
+
+#[test]
+#[should_panic(expected = "there's no en.ftl")]
+fn no_fallback() {
+    generate(&[("ru.ftl", "x = a")]);
+}
+
+#[test]
+fn plural_rules() {
+    let of = |lang, numbers: &[u64]| -> Vec<_> {
+        numbers.iter().map(|&n| plural::category(lang, n)).collect()
+    };
+    assert_eq!(
+        of("en", &[0, 1, 2, 11, 21]),
+        ["other", "one", "other", "other", "other"]
+    );
+    assert_eq!(
+        of(
+            "ru",
+            &[0, 1, 2, 4, 5, 11, 12, 14, 21, 22, 25, 101, 111, 112, 122]
+        ),
+        [
+            "many", "one", "few", "few", "many", "many", "many", "many", "one", "few", "many",
+            "one", "many", "many", "few"
+        ]
+    );
+    assert_eq!(
+        of("pl", &[0, 1, 2, 5, 12, 21, 22]),
+        ["many", "one", "few", "many", "many", "many", "few"]
+    );
+    assert_eq!(of("cs", &[1, 3, 5]), ["one", "few", "other"]);
+    assert_eq!(of("ja", &[1, 2]), ["other", "other"]);
+}
@@ -0,0 +1,89 @@
+//! The build script and the `source` feature share the reader, so it's included directly.
+
+#[path = "../voidsn.rs"]
+mod voidsn;
+
+/// 2x2, an opaque red background under a layer with a blue, a transparent, a color keyed
+/// (magenta) and a half transparent green pixel.
+const TWO_LAYERS: &[u8] = include_bytes!("two_layers.voidsn");
+
+#[test]
+fn flatten_two_layers() {
+    let image = voidsn::flatten(TWO_LAYERS).unwrap();
+    assert_eq!((image.width, image.height), (2, 2));
+    assert_eq!(
+        image.rgba,
+        [
+            [0, 0, 255, 255],
+            [255, 0, 0, 255],
+            [255, 0, 0, 255],
+            [127, 128, 0, 255],
+        ]
+        .concat()
+    );
+}
+
+#[test]
+fn hidden_layer() {
+    let mut bytes = TWO_LAYERS.to_vec();
+    let at = bytes.windows(2).position(|x| x == b"11").unwrap();
+    bytes[at + 1] = b'0';
+    let image = voidsn::flatten(&bytes).unwrap();
+    assert_eq!(image.rgba, [255, 0, 0, 255].repeat(4));
+}
+
+/// The fixture with its `255;255` opacities replaced by `opacity`, of the same length.
+fn with_opacity(opacity: &[u8]) -> Vec<u8> {
+    let mut bytes = TWO_LAYERS.to_vec();
+    let at = bytes.windows(7).position(|x| x == b"255;255").unwrap();
+    bytes[at..at + 7].copy_from_slice(opacity);
+    bytes
+}
+
+#[test]
+fn opacity() {
+    // A trailing separator doesn't shift the top layer onto the background's value.
+    let image = voidsn::flatten(&with_opacity(b"255;00;")).unwrap();
+    assert_eq!(image.rgba, [255, 0, 0, 255].repeat(4));
+}
+
+#[test]
+fn invalid_opacity() {
+    for (opacity, expected) in [
+        (b"2x5;128", "layer 0 has invalid opacity \"2x5\""),
+        (b"255;;64", "layer 1 has invalid opacity \"\""),
+    ] {
+        let why = voidsn::flatten(&with_opacity(opacity)).err().unwrap();
+        assert_eq!(why, expected);
+    }
+}
+
+#[test]
+fn to_png() {
+    let png = voidsn::to_png(TWO_LAYERS).unwrap();
+    assert!(png.starts_with(b"\x89PNG"));
+}
+
+#[test]
+fn truncated_layer() {
+    let why = voidsn::flatten(&TWO_LAYERS[..TWO_LAYERS.len() - 1])
+        .err()
+        .unwrap();
+    assert_eq!(
+        why,
+        "layer 1 has 15 bytes of pixels, expected 16 (2x2 ARGB)"
+    );
+}
+
+#[test]
+fn truncated_header() {
+    assert!(voidsn::flatten(&TWO_LAYERS[..20]).is_err());
+    assert!(voidsn::flatten(&[]).is_err());
+}
+
+#[test]
+fn unsupported_version() {
+    let mut bytes = TWO_LAYERS.to_vec();
+    bytes[0] = 2;
+    assert!(voidsn::flatten(&bytes).is_err());
+}
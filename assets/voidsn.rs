@@ -0,0 +1,142 @@
+//! Reader for voidsprite `.voidsn` project files.
+//!
+//! Shared between the build script and the `source` feature, so keep it dependency-free
+//! (apart from `png`).
+
+use std::{collections::HashMap, io::Cursor};
+
+/// A flattened RGBA8 image.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+struct Reader<'a>(&'a [u8]);
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.0.len() < len {
+            return Err("unexpected end of file".into());
+        }
+        let (x, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(x)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<&'a str, String> {
+        let len = self.u32()? as usize;
+        std::str::from_utf8(self.bytes(len)?).map_err(|x| x.to_string())
+    }
+}
+
+/// Flatten all visible layers of a `.voidsn` file into a single image.
+///
+/// Only version 3 of the format is supported.
+pub fn flatten(bytes: &[u8]) -> Result<Image, String> {
+    let mut reader = Reader(bytes);
+
+    let version = reader.u8()?;
+    if version != 3 {
+        return Err(format!("unsupported voidsn version {version}"));
+    }
+    let width = reader.u32()?;
+    let height = reader.u32()?;
+
+    let mut meta = HashMap::new();
+    if reader.bytes(13)? != b"/VOIDSN.META/" {
+        return Err("missing metadata".into());
+    }
+    for _ in 0..reader.u32()? {
+        let key = reader.string()?;
+        let value = reader.string()?;
+        meta.insert(key, value);
+    }
+
+    // Per-layer values. Visibility is one character per layer, opacity is `;`-separated.
+    let visibility: Vec<bool> = meta
+        .get("layer.visibility")
+        .map(|x| x.chars().filter(|x| *x != ';').map(|x| x != '0').collect())
+        .unwrap_or_default();
+    let opacity: Vec<u8> = meta
+        .get("layer.opacity")
+        .copied()
+        .unwrap_or_default()
+        .split_terminator(';')
+        .enumerate()
+        .map(|(i, x)| {
+            x.parse()
+                .map_err(|_| format!("layer {i} has invalid opacity {x:?}"))
+        })
+        .collect::<Result<_, _>>()?;
+
+    let len = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|x| x.checked_mul(4))
+        .ok_or_else(|| format!("{width}x{height} is too big"))?;
+    let mut rgba = vec![0u8; len];
+    for layer in 0..reader.u32()? as usize {
+        let _name = reader.string()?;
+        let color_key = (reader.u8()? != 0, reader.u32()?);
+        if reader.0.len() < len {
+            return Err(format!(
+                "layer {layer} has {} bytes of pixels, expected {len} ({width}x{height} ARGB)",
+                reader.0.len()
+            ));
+        }
+        let pixels = reader.bytes(len)?;
+        if !visibility.get(layer).copied().unwrap_or(true) {
+            continue;
+        }
+        let opacity = opacity.get(layer).copied().unwrap_or(255) as u32;
+
+        for (dst, src) in rgba.chunks_exact_mut(4).zip(pixels.chunks_exact(4)) {
+            // Pixels are stored as little-endian ARGB.
+            let argb = u32::from_le_bytes(src.try_into().unwrap());
+            if color_key.0 && argb == color_key.1 {
+                continue;
+            }
+            let [b, g, r, a] = argb.to_le_bytes();
+            let a = a as u32 * opacity / 255;
+            if a == 0 {
+                continue;
+            }
+
+            let da = dst[3] as u32 * (255 - a) / 255;
+            let oa = a + da;
+            for (d, s) in dst.iter_mut().zip([r, g, b]) {
+                *d = ((s as u32 * a + *d as u32 * da) / oa) as u8;
+            }
+            dst[3] = oa as u8;
+        }
+    }
+
+    Ok(Image {
+        width,
+        height,
+        rgba,
+    })
+}
+
+/// Flatten a `.voidsn` file into a png image.
+pub fn to_png(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let image = flatten(bytes)?;
+    let mut out = Cursor::new(vec![]);
+    let mut encoder = png::Encoder::new(&mut out, image.width, image.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_compression(png::Compression::Best);
+    encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive);
+    encoder
+        .write_header()
+        .and_then(|mut x| x.write_image_data(&image.rgba))
+        .map_err(|x| x.to_string())?;
+    Ok(out.into_inner())
+}
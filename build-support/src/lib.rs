@@ -0,0 +1,33 @@
+//! Helpers shared by the build scripts of `assets` and `server`.
+
+/// 64-bit FNV-1a. Only used to tell file versions apart.
+pub fn hash(bytes: &[u8]) -> String {
+    let mut hash = 0xcbf29ce484222325u64;
+    for x in bytes {
+        hash ^= *x as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{hash:016x}")
+}
+
+/// Gzip and brotli variants of `bytes` by file extension (`gz`, `br`), `None` if one isn't
+/// smaller than the original.
+#[cfg(feature = "compress")]
+pub fn compress(bytes: &[u8]) -> [(&'static str, Option<Vec<u8>>); 2] {
+    use std::io::Write;
+
+    use brotli::CompressorWriter;
+    use flate2::{write::GzEncoder, Compression};
+
+    let gzip = {
+        let mut encoder = GzEncoder::new(vec![], Compression::best());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    };
+    let br = {
+        let mut encoder = CompressorWriter::new(vec![], 4096, 11, 22);
+        encoder.write_all(bytes).unwrap();
+        encoder.into_inner()
+    };
+    [("gz", gzip), ("br", br)].map(|(ext, x)| (ext, Some(x).filter(|x| x.len() < bytes.len())))
+}
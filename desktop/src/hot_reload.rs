@@ -0,0 +1,78 @@
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::mpsc::{channel, Receiver},
+};
+
+use app::{data::Data, util::Result};
+use assets::{ResourceMut, Resources, MANIFEST, SOURCE_DIR};
+use log::{info, warn};
+use notify::{recommended_watcher, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use skia_safe::{Data as SkData, FontMgr, Image, Typeface};
+
+use crate::{load_png, load_ttf};
+
+/// Watches [`SOURCE_DIR`] and swaps changed resources in place.
+pub struct HotReload {
+    _watcher: RecommendedWatcher,
+    changes: Receiver<String>,
+}
+impl HotReload {
+    pub fn new() -> Result<Self> {
+        let (tx, changes) = channel();
+        let mut watcher = recommended_watcher(move |event: notify::Result<Event>| {
+            let event = match event {
+                Ok(x) => x,
+                Err(why) => {
+                    warn!("Watcher failure: {why}");
+                    return;
+                }
+            };
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                return;
+            }
+            for x in event.paths {
+                if let Some(x) = assets::resource_path(&x) {
+                    let _ = tx.send(x);
+                }
+            }
+        })?;
+        watcher.watch(Path::new(SOURCE_DIR), RecursiveMode::Recursive)?;
+        info!("Watching {SOURCE_DIR} for changes");
+
+        Ok(Self {
+            _watcher: watcher,
+            changes,
+        })
+    }
+
+    /// Reload all resources that were changed since last call.
+    pub fn apply(&self, resources: &mut Resources<Image, Typeface, Data>, font_mgr: &FontMgr) {
+        let paths: HashSet<String> = self.changes.try_iter().collect();
+        for path in paths {
+            let Some(resource) = resources.by_path_mut(&path) else {
+                continue;
+            };
+            let bytes = match assets::read_source(&path) {
+                Ok(x) => x,
+                Err(why) => {
+                    warn!("Failed to read '{path}': {why}");
+                    continue;
+                }
+            };
+            let loaded = match resource {
+                ResourceMut::Png(x) => load_png(SkData::new_copy(&bytes)).map(|y| *x = y).is_some(),
+                ResourceMut::Ttf(x) => load_ttf(font_mgr, &bytes).map(|y| *x = y).is_some(),
+                ResourceMut::Data(x) => {
+                    let mime = MANIFEST.iter().find(|x| x.path == path).unwrap().mime;
+                    Data::load(&path, mime, &bytes).map(|y| *x = y).is_ok()
+                }
+            };
+            if loaded {
+                info!("Reloaded '{path}'");
+            } else {
+                warn!("Failed to decode '{path}'");
+            }
+        }
+    }
+}
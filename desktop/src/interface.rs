@@ -1,7 +1,8 @@
-use std::{collections::VecDeque, ffi::c_void, time::SystemTime};
+use std::{collections::VecDeque, env, ffi::c_void, time::SystemTime};
 
-use app::{util::Result, Event, Interface, KeyState};
+use app::{util::Result, Control, Event, Interface, KeyState};
 use gl::types::GLint;
+use protocol::ClientMessage;
 use sdl2::{
     keyboard::Scancode,
     video::{GLContext, Window},
@@ -17,6 +18,18 @@ use skia_safe::{
     Color4f, ColorType, Font, Image, Paint, Surface, TextBlob, Typeface,
 };
 
+use crate::net::Connection;
+
+fn control(scancode: Scancode) -> Option<Control> {
+    match scancode {
+        Scancode::W | Scancode::Up => Some(Control::Up),
+        Scancode::S | Scancode::Down => Some(Control::Down),
+        Scancode::A | Scancode::Left => Some(Control::Left),
+        Scancode::D | Scancode::Right => Some(Control::Right),
+        _ => None,
+    }
+}
+
 fn create_surface(
     window: &Window,
     fb_info: FramebufferInfo,
@@ -56,6 +69,7 @@ pub struct SdlInterface {
     window: Window,
     video: VideoSubsystem,
     _sdl: Sdl,
+    connection: Option<Connection>,
 }
 impl SdlInterface {
     pub fn new() -> Result<Self> {
@@ -118,6 +132,8 @@ impl SdlInterface {
             time: SystemTime::now(),
             last_frame_time: SystemTime::now(),
             event_queue: VecDeque::with_capacity(32),
+            // e.g. `ws://127.0.0.1:8080/ws`
+            connection: env::var("USMG_SERVER").ok().map(Connection::spawn),
         })
     }
 
@@ -172,6 +188,10 @@ impl SdlInterface {
                         self.event_queue
                             .push_front(Event::Input(app::GenericKey::Send));
                     }
+                    if let Some(x) = control(scancode).filter(|_| !repeat) {
+                        self.event_queue
+                            .push_front(Event::Control(x, KeyState::Pressed));
+                    }
                     self.event_queue.push_front(Event::Key {
                         key: scancode,
                         state: KeyState::Pressed,
@@ -183,6 +203,10 @@ impl SdlInterface {
                     repeat,
                     ..
                 } => {
+                    if let Some(x) = control(scancode) {
+                        self.event_queue
+                            .push_front(Event::Control(x, KeyState::Released));
+                    }
                     self.event_queue.push_front(Event::Key {
                         key: scancode,
                         state: KeyState::Released,
@@ -196,6 +220,12 @@ impl SdlInterface {
                 _ => (),
             }
         }
+
+        if let Some(x) = &self.connection {
+            for x in x.poll() {
+                self.event_queue.push_front(x);
+            }
+        }
     }
 
     pub fn swap(&mut self) {
@@ -233,6 +263,12 @@ impl Interface for SdlInterface {
         true
     }
 
+    fn send(&mut self, message: ClientMessage) {
+        if let Some(x) = &self.connection {
+            x.send(message);
+        }
+    }
+
     fn held(&self, key: &Self::Key) -> bool {
         // TODO: Handle keyboard keys
         false
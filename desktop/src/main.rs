@@ -1,20 +1,53 @@
+#[cfg(all(debug_assertions, feature = "hot-reload"))]
+mod hot_reload;
 mod interface;
+mod net;
 
-use std::{thread::sleep, time::Duration};
+use std::{borrow::Cow, env, thread::sleep, time::Duration};
 
-use app::{game::Flow, util::Result, Application};
-use assets::include_resources;
+use app::{data::Data, game::Flow, util::Result, Application};
+use assets::{include_resources, lang::Locale, Abstract};
 use interface::SdlInterface;
-use skia_safe::{Data, FontMgr, Image};
+use skia_safe::{Data as SkData, FontMgr, Image, Typeface};
+
+/// Resource bytes. Read from disk instead when hot-reloading.
+fn read(x: &Abstract) -> Cow<'static, [u8]> {
+    #[cfg(all(debug_assertions, feature = "hot-reload"))]
+    match assets::read_source(x.path) {
+        Ok(x) => return Cow::Owned(x),
+        Err(why) => log::warn!("Failed to read '{}' from disk: {why}", x.path),
+    }
+    Cow::Borrowed(x.bytes)
+}
+
+/// Skia data of a resource, copied only when it's read from disk for hot-reloading.
+fn sk_data(x: &Abstract) -> SkData {
+    match read(x) {
+        Cow::Owned(x) => SkData::new_copy(&x),
+        // Embedded bytes live as long as the program.
+        Cow::Borrowed(x) => unsafe { SkData::new_bytes(x) },
+    }
+}
+
+fn load_png(data: SkData) -> Option<Image> {
+    Image::from_encoded(data)
+}
+
+fn load_ttf(font_mgr: &FontMgr, bytes: &[u8]) -> Option<Typeface> {
+    font_mgr.new_from_data(bytes, None)
+}
 
 fn main() -> Result {
     pretty_env_logger::init();
 
     let font_mgr = FontMgr::new();
     let resources = include_resources! {
-        x.png => Image::from_encoded(unsafe { Data::new_bytes(x.bytes) }).expect("Failed to load png image"),
-        x.ttf => font_mgr.new_from_data(x.bytes, None).expect("Failed to load font"),
+        x.png => load_png(sk_data(&x)).expect("Failed to load png image"),
+        x.ttf => load_ttf(&font_mgr, &read(&x)).expect("Failed to load font"),
+        x.data => Data::load(x.path, x.mime, &read(&x)).expect("Failed to load data"),
     };
+    #[cfg(all(debug_assertions, feature = "hot-reload"))]
+    let hot_reload = hot_reload::HotReload::new()?;
     let interface = SdlInterface::new()?;
     let mut application = Application {
         game: Default::default(),
@@ -22,7 +55,18 @@ fn main() -> Result {
         resources,
     };
 
+    // Same lookup order as gettext
+    if let Some(x) = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .find_map(|x| env::var(x).ok().filter(|x| !x.is_empty()))
+        .and_then(|x| Locale::from_code(&x))
+    {
+        application.game.set_locale(x);
+    }
+
     loop {
+        #[cfg(all(debug_assertions, feature = "hot-reload"))]
+        hot_reload.apply(&mut application.resources, &font_mgr);
         application.interface.reset();
         match application.tick()? {
             Flow::Continue => (),
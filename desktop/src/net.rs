@@ -0,0 +1,88 @@
+use std::{
+    io::ErrorKind,
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use app::Event;
+use log::{info, warn};
+use protocol::ClientMessage;
+use tungstenite::{stream::MaybeTlsStream, Error, Message as Frame};
+
+use crate::interface::SdlInterface;
+
+/// How long to wait for incoming messages before sending queued ones.
+const POLL_DELAY: Duration = Duration::from_millis(5);
+
+/// WebSocket connection to the game server, running on its own thread.
+pub struct Connection {
+    outgoing: Sender<ClientMessage>,
+    incoming: Receiver<Event<SdlInterface>>,
+}
+impl Connection {
+    /// Connect to `url` (`ws://<host>:<port>/ws`) in the background.
+    pub fn spawn(url: String) -> Self {
+        let (outgoing, rx) = channel::<ClientMessage>();
+        let (tx, incoming) = channel();
+        thread::spawn(move || {
+            let mut socket = match tungstenite::connect(&url) {
+                Ok((x, _)) => x,
+                Err(why) => {
+                    warn!("Failed to connect to '{url}': {why}");
+                    let _ = tx.send(Event::Disconnected);
+                    return;
+                }
+            };
+            if let MaybeTlsStream::Plain(x) = socket.get_ref() {
+                x.set_read_timeout(Some(POLL_DELAY)).unwrap();
+            }
+            info!("Connected to '{url}'");
+            let _ = tx.send(Event::Connected);
+
+            'outer: loop {
+                for x in rx.try_iter() {
+                    if let Err(why) = socket.send(Frame::Binary(protocol::encode(&x))) {
+                        warn!("Failed to send message: {why}");
+                        break 'outer;
+                    }
+                }
+                let message = match socket.read() {
+                    Ok(Frame::Binary(x)) => protocol::decode(&x),
+                    Ok(_) => continue,
+                    Err(Error::Io(x))
+                        if matches!(x.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                    {
+                        continue
+                    }
+                    Err(Error::ConnectionClosed) => break,
+                    Err(why) => {
+                        warn!("Connection failure: {why}");
+                        break;
+                    }
+                };
+                let event = match message {
+                    Ok(x) => Event::Message(x),
+                    Err(why) => {
+                        warn!("Bad message from server: {why}");
+                        continue;
+                    }
+                };
+                if tx.send(event).is_err() {
+                    break;
+                }
+            }
+            let _ = tx.send(Event::Disconnected);
+        });
+        Self { outgoing, incoming }
+    }
+
+    pub fn send(&self, message: ClientMessage) {
+        let _ = self.outgoing.send(message);
+    }
+
+    /// Events received since last call.
+    pub fn poll(&self) -> impl Iterator<Item = Event<SdlInterface>> + '_ {
+        self.incoming.try_iter()
+    }
+}
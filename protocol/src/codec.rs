@@ -0,0 +1,218 @@
+//! Binary encoding.
+//!
+//! Unsigned integers are LEB128 varints, signed ones are zigzag-encoded first. Strings and lists
+//! are prefixed with their length. Nothing depends on pointer width or endianness, so native and
+//! wasm builds produce the same bytes.
+
+use std::{error::Error, fmt::Display};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DecodeError {
+    /// Unknown message or enum tag.
+    Tag(u8),
+    /// Input ended early.
+    Truncated,
+    /// Bytes left after the message.
+    Trailing,
+    /// A varint doesn't fit its type.
+    Overflow,
+    /// A varint has redundant trailing zero groups, so encodings stay unique.
+    Overlong,
+    /// A string isn't valid UTF-8.
+    Utf8,
+}
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tag(x) => write!(f, "unknown tag {x}"),
+            Self::Truncated => f.write_str("message is truncated"),
+            Self::Trailing => f.write_str("trailing bytes after message"),
+            Self::Overflow => f.write_str("integer overflow"),
+            Self::Overlong => f.write_str("overlong varint"),
+            Self::Utf8 => f.write_str("string is not valid UTF-8"),
+        }
+    }
+}
+impl Error for DecodeError {}
+
+#[derive(Default)]
+pub struct Writer(pub Vec<u8>);
+impl Writer {
+    pub fn u8(&mut self, x: u8) {
+        self.0.push(x);
+    }
+
+    pub fn u64(&mut self, mut x: u64) {
+        while x >= 0x80 {
+            self.0.push(x as u8 | 0x80);
+            x >>= 7;
+        }
+        self.0.push(x as u8);
+    }
+
+    pub fn u32(&mut self, x: u32) {
+        self.u64(x as u64);
+    }
+
+    pub fn i32(&mut self, x: i32) {
+        self.u32(((x << 1) ^ (x >> 31)) as u32);
+    }
+
+    pub fn length(&mut self, x: usize) {
+        self.u64(x as u64);
+    }
+
+    pub fn bool(&mut self, x: bool) {
+        self.u8(x as u8);
+    }
+
+    pub fn str(&mut self, x: &str) {
+        self.length(x.len());
+        self.0.extend(x.as_bytes());
+    }
+}
+
+pub struct Reader<'a>(pub &'a [u8]);
+impl<'a> Reader<'a> {
+    pub fn u8(&mut self) -> Result<u8, DecodeError> {
+        let (x, rest) = self.0.split_first().ok_or(DecodeError::Truncated)?;
+        self.0 = rest;
+        Ok(*x)
+    }
+
+    pub fn u64(&mut self) -> Result<u64, DecodeError> {
+        let mut x = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            let bits = (byte & 0x7f) as u64;
+            if bits << shift >> shift != bits {
+                return Err(DecodeError::Overflow);
+            }
+            x |= bits << shift;
+            if byte & 0x80 == 0 {
+                if byte == 0 && shift > 0 {
+                    return Err(DecodeError::Overlong);
+                }
+                return Ok(x);
+            }
+        }
+        Err(DecodeError::Overflow)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, DecodeError> {
+        self.u64()?.try_into().map_err(|_| DecodeError::Overflow)
+    }
+
+    pub fn i32(&mut self) -> Result<i32, DecodeError> {
+        let x = self.u32()?;
+        Ok((x >> 1) as i32 ^ -((x & 1) as i32))
+    }
+
+    /// Length of a string or list. Never more than the bytes left, so it's safe to allocate.
+    pub fn length(&mut self) -> Result<usize, DecodeError> {
+        let x = self.u64()?;
+        if x > self.0.len() as u64 {
+            return Err(DecodeError::Truncated);
+        }
+        Ok(x as usize)
+    }
+
+    pub fn bool(&mut self) -> Result<bool, DecodeError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            x => Err(DecodeError::Tag(x)),
+        }
+    }
+
+    pub fn str(&mut self) -> Result<String, DecodeError> {
+        let len = self.length()?;
+        let (x, rest) = self.0.split_at(len);
+        self.0 = rest;
+        String::from_utf8(x.to_vec()).map_err(|_| DecodeError::Utf8)
+    }
+}
+
+pub trait Encode {
+    fn encode(&self, w: &mut Writer);
+}
+
+pub trait Decode: Sized {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError>;
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, w: &mut Writer) {
+        w.length(self.len());
+        for x in self {
+            x.encode(w);
+        }
+    }
+}
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+        // Every element takes at least a byte, so `length` bounds the allocation.
+        let len = r.length()?;
+        let mut vec = Vec::with_capacity(len);
+        for _ in 0..len {
+            vec.push(T::decode(r)?);
+        }
+        Ok(vec)
+    }
+}
+
+impl Encode for u32 {
+    fn encode(&self, w: &mut Writer) {
+        w.u32(*self);
+    }
+}
+impl Decode for u32 {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+        r.u32()
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, w: &mut Writer) {
+        w.str(self);
+    }
+}
+impl Decode for String {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+        r.str()
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, w: &mut Writer) {
+        w.bool(self.is_some());
+        if let Some(x) = self {
+            x.encode(w);
+        }
+    }
+}
+impl<T: Decode> Decode for Option<T> {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(match r.bool()? {
+            true => Some(T::decode(r)?),
+            false => None,
+        })
+    }
+}
+
+/// Encode a message into a frame.
+pub fn encode(x: &impl Encode) -> Vec<u8> {
+    let mut w = Writer::default();
+    x.encode(&mut w);
+    w.0
+}
+
+/// Decode a frame holding exactly one message.
+pub fn decode<T: Decode>(bytes: &[u8]) -> Result<T, DecodeError> {
+    let mut r = Reader(bytes);
+    let x = T::decode(&mut r)?;
+    if !r.0.is_empty() {
+        return Err(DecodeError::Trailing);
+    }
+    Ok(x)
+}
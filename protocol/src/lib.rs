@@ -0,0 +1,461 @@
+//! Messages exchanged between game clients and the server.
+//!
+//! Every message is a single binary WebSocket frame: a tag byte followed by its fields, see
+//! [`codec`] for how those are laid out. The client starts with [`ClientMessage::Hello`] and may
+//! send anything else only after the server replies with [`ServerMessage::Welcome`]. Game
+//! messages are only handled after joining a room ([`ServerMessage::Joined`]).
+
+pub mod codec;
+
+pub use codec::{decode, encode, DecodeError};
+
+use codec::{Decode, Encode, Reader, Writer};
+
+/// Protocol version. Bump on every incompatible change.
+pub const VERSION: u32 = 4;
+/// WebSocket endpoint on the server.
+pub const PATH: &str = "/ws";
+
+/// Held keys, one bit each.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Keys(pub u8);
+impl Keys {
+    pub const UP: Keys = Keys(1);
+    pub const DOWN: Keys = Keys(2);
+    pub const LEFT: Keys = Keys(4);
+    pub const RIGHT: Keys = Keys(8);
+
+    pub fn contains(self, other: Keys) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn set(&mut self, other: Keys, held: bool) {
+        if held {
+            self.0 |= other.0;
+        } else {
+            self.0 &= !other.0;
+        }
+    }
+}
+
+/// Client input for a single tick.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Input {
+    /// Increases by one every input, so the server can tell which ones it has applied.
+    pub seq: u32,
+    pub keys: Keys,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Entity {
+    pub id: u32,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Full world state.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Snapshot {
+    pub tick: u32,
+    /// Last input of the receiving client applied to this state.
+    pub ack: u32,
+    pub entities: Vec<Entity>,
+}
+
+/// World state relative to an earlier tick.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Delta {
+    pub tick: u32,
+    /// Tick this delta applies to.
+    pub base: u32,
+    /// Last input of the receiving client applied to this state.
+    pub ack: u32,
+    /// Entities that were added or changed.
+    pub changed: Vec<Entity>,
+    /// Ids of removed entities.
+    pub removed: Vec<u32>,
+}
+
+/// A room as seen in the room list.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct RoomInfo {
+    pub id: u32,
+    pub name: String,
+    pub players: u32,
+    pub max_players: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum ErrorCode {
+    /// Client speaks another protocol version.
+    Version,
+    /// Message couldn't be decoded.
+    Malformed,
+    /// Message isn't allowed right now.
+    Unexpected,
+    /// No room with such id or code.
+    NotFound,
+    /// Room has no free slots.
+    Full,
+    /// Server can't host any more rooms.
+    Limit,
+    /// Only the room host may do that.
+    NotHost,
+}
+impl ErrorCode {
+    /// Whether the server closes the connection after this error.
+    pub fn is_fatal(self) -> bool {
+        matches!(self, Self::Version | Self::Malformed | Self::Unexpected)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ClientMessage {
+    Hello {
+        version: u32,
+        name: String,
+    },
+    Input(Input),
+    Chat(String),
+    Ping(u32),
+    Pong(u32),
+    /// Ask for public rooms.
+    ListRooms,
+    /// Create a room and join it. Private rooms aren't listed and are joined by code.
+    CreateRoom {
+        name: String,
+        private: bool,
+        max_players: u32,
+    },
+    JoinRoom(u32),
+    JoinCode(String),
+    /// Join any public room with free slots, creating one if there's none.
+    QuickMatch,
+    LeaveRoom,
+    /// Remove a player from the room. Only the host may.
+    Kick(u32),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ServerMessage {
+    /// Handshake accepted, `id` is the client's entity.
+    Welcome {
+        id: u32,
+    },
+    Snapshot(Snapshot),
+    Delta(Delta),
+    Chat {
+        from: u32,
+        text: String,
+    },
+    /// Something went wrong. The connection is closed afterwards if `code` is fatal.
+    Error {
+        code: ErrorCode,
+        message: String,
+    },
+    Ping(u32),
+    Pong(u32),
+    /// Reply to [`ClientMessage::ListRooms`].
+    Rooms(Vec<RoomInfo>),
+    /// Joined a room. `code` is set for private rooms.
+    Joined {
+        room: RoomInfo,
+        code: Option<String>,
+        host: u32,
+        tick: u32,
+    },
+    /// Left the room.
+    Left,
+    /// Room host has changed.
+    Host(u32),
+    /// Removed from the room with this id by its host.
+    Kicked(u32),
+}
+
+impl Encode for Input {
+    fn encode(&self, w: &mut Writer) {
+        w.u32(self.seq);
+        w.u8(self.keys.0);
+    }
+}
+impl Decode for Input {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Self {
+            seq: r.u32()?,
+            keys: Keys(r.u8()?),
+        })
+    }
+}
+
+impl Encode for Entity {
+    fn encode(&self, w: &mut Writer) {
+        w.u32(self.id);
+        w.i32(self.x);
+        w.i32(self.y);
+    }
+}
+impl Decode for Entity {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Self {
+            id: r.u32()?,
+            x: r.i32()?,
+            y: r.i32()?,
+        })
+    }
+}
+
+impl Encode for Snapshot {
+    fn encode(&self, w: &mut Writer) {
+        w.u32(self.tick);
+        w.u32(self.ack);
+        self.entities.encode(w);
+    }
+}
+impl Decode for Snapshot {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Self {
+            tick: r.u32()?,
+            ack: r.u32()?,
+            entities: Decode::decode(r)?,
+        })
+    }
+}
+
+impl Encode for Delta {
+    fn encode(&self, w: &mut Writer) {
+        w.u32(self.tick);
+        w.u32(self.base);
+        w.u32(self.ack);
+        self.changed.encode(w);
+        self.removed.encode(w);
+    }
+}
+impl Decode for Delta {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Self {
+            tick: r.u32()?,
+            base: r.u32()?,
+            ack: r.u32()?,
+            changed: Decode::decode(r)?,
+            removed: Decode::decode(r)?,
+        })
+    }
+}
+
+impl Encode for RoomInfo {
+    fn encode(&self, w: &mut Writer) {
+        w.u32(self.id);
+        w.str(&self.name);
+        w.u32(self.players);
+        w.u32(self.max_players);
+    }
+}
+impl Decode for RoomInfo {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(Self {
+            id: r.u32()?,
+            name: r.str()?,
+            players: r.u32()?,
+            max_players: r.u32()?,
+        })
+    }
+}
+
+impl Encode for ErrorCode {
+    fn encode(&self, w: &mut Writer) {
+        w.u8(match self {
+            Self::Version => 0,
+            Self::Malformed => 1,
+            Self::Unexpected => 2,
+            Self::NotFound => 3,
+            Self::Full => 4,
+            Self::Limit => 5,
+            Self::NotHost => 6,
+        });
+    }
+}
+impl Decode for ErrorCode {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(match r.u8()? {
+            0 => Self::Version,
+            1 => Self::Malformed,
+            2 => Self::Unexpected,
+            3 => Self::NotFound,
+            4 => Self::Full,
+            5 => Self::Limit,
+            6 => Self::NotHost,
+            x => return Err(DecodeError::Tag(x)),
+        })
+    }
+}
+
+impl Encode for ClientMessage {
+    fn encode(&self, w: &mut Writer) {
+        match self {
+            Self::Hello { version, name } => {
+                w.u8(0);
+                w.u32(*version);
+                w.str(name);
+            }
+            Self::Input(x) => {
+                w.u8(1);
+                x.encode(w);
+            }
+            Self::Chat(x) => {
+                w.u8(2);
+                w.str(x);
+            }
+            Self::Ping(x) => {
+                w.u8(3);
+                w.u32(*x);
+            }
+            Self::Pong(x) => {
+                w.u8(4);
+                w.u32(*x);
+            }
+            Self::ListRooms => w.u8(5),
+            Self::CreateRoom {
+                name,
+                private,
+                max_players,
+            } => {
+                w.u8(6);
+                w.str(name);
+                w.bool(*private);
+                w.u32(*max_players);
+            }
+            Self::JoinRoom(x) => {
+                w.u8(7);
+                w.u32(*x);
+            }
+            Self::JoinCode(x) => {
+                w.u8(8);
+                w.str(x);
+            }
+            Self::QuickMatch => w.u8(9),
+            Self::LeaveRoom => w.u8(10),
+            Self::Kick(x) => {
+                w.u8(11);
+                w.u32(*x);
+            }
+        }
+    }
+}
+impl Decode for ClientMessage {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(match r.u8()? {
+            0 => Self::Hello {
+                version: r.u32()?,
+                name: r.str()?,
+            },
+            1 => Self::Input(Input::decode(r)?),
+            2 => Self::Chat(r.str()?),
+            3 => Self::Ping(r.u32()?),
+            4 => Self::Pong(r.u32()?),
+            5 => Self::ListRooms,
+            6 => Self::CreateRoom {
+                name: r.str()?,
+                private: r.bool()?,
+                max_players: r.u32()?,
+            },
+            7 => Self::JoinRoom(r.u32()?),
+            8 => Self::JoinCode(r.str()?),
+            9 => Self::QuickMatch,
+            10 => Self::LeaveRoom,
+            11 => Self::Kick(r.u32()?),
+            x => return Err(DecodeError::Tag(x)),
+        })
+    }
+}
+
+impl Encode for ServerMessage {
+    fn encode(&self, w: &mut Writer) {
+        match self {
+            Self::Welcome { id } => {
+                w.u8(0);
+                w.u32(*id);
+            }
+            Self::Snapshot(x) => {
+                w.u8(1);
+                x.encode(w);
+            }
+            Self::Delta(x) => {
+                w.u8(2);
+                x.encode(w);
+            }
+            Self::Chat { from, text } => {
+                w.u8(3);
+                w.u32(*from);
+                w.str(text);
+            }
+            Self::Error { code, message } => {
+                w.u8(4);
+                code.encode(w);
+                w.str(message);
+            }
+            Self::Ping(x) => {
+                w.u8(5);
+                w.u32(*x);
+            }
+            Self::Pong(x) => {
+                w.u8(6);
+                w.u32(*x);
+            }
+            Self::Rooms(x) => {
+                w.u8(7);
+                x.encode(w);
+            }
+            Self::Joined {
+                room,
+                code,
+                host,
+                tick,
+            } => {
+                w.u8(8);
+                room.encode(w);
+                code.encode(w);
+                w.u32(*host);
+                w.u32(*tick);
+            }
+            Self::Left => w.u8(9),
+            Self::Host(x) => {
+                w.u8(10);
+                w.u32(*x);
+            }
+            Self::Kicked(x) => {
+                w.u8(11);
+                w.u32(*x);
+            }
+        }
+    }
+}
+impl Decode for ServerMessage {
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(match r.u8()? {
+            0 => Self::Welcome { id: r.u32()? },
+            1 => Self::Snapshot(Snapshot::decode(r)?),
+            2 => Self::Delta(Delta::decode(r)?),
+            3 => Self::Chat {
+                from: r.u32()?,
+                text: r.str()?,
+            },
+            4 => Self::Error {
+                code: ErrorCode::decode(r)?,
+                message: r.str()?,
+            },
+            5 => Self::Ping(r.u32()?),
+            6 => Self::Pong(r.u32()?),
+            7 => Self::Rooms(Decode::decode(r)?),
+            8 => Self::Joined {
+                room: RoomInfo::decode(r)?,
+                code: Decode::decode(r)?,
+                host: r.u32()?,
+                tick: r.u32()?,
+            },
+            9 => Self::Left,
+            10 => Self::Host(r.u32()?),
+            11 => Self::Kicked(r.u32()?),
+            x => return Err(DecodeError::Tag(x)),
+        })
+    }
+}
@@ -0,0 +1,249 @@
+use usmg_protocol::{
+    codec::{Reader, Writer},
+    decode, encode, ClientMessage, Delta, Entity, ErrorCode, Input, Keys, RoomInfo, ServerMessage,
+    Snapshot, VERSION,
+};
+
+/// Deterministic xorshift64 so failures are reproducible.
+struct Rng(u64);
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, x: u64) -> u64 {
+        self.next() % x
+    }
+
+    /// Biased towards edge cases.
+    fn u32(&mut self) -> u32 {
+        match self.below(4) {
+            0 => [0, 1, 127, 128, u32::MAX][self.below(5) as usize],
+            1 => self.below(256) as u32,
+            _ => self.next() as u32,
+        }
+    }
+
+    fn i32(&mut self) -> i32 {
+        match self.below(4) {
+            0 => [0, -1, 1, i32::MIN, i32::MAX][self.below(5) as usize],
+            _ => self.u32() as i32,
+        }
+    }
+
+    fn string(&mut self) -> String {
+        (0..self.below(16))
+            .map(|_| ['a', 'я', '€', '🦀', '\0'][self.below(5) as usize])
+            .collect()
+    }
+
+    fn entities(&mut self) -> Vec<Entity> {
+        (0..self.below(8))
+            .map(|_| Entity {
+                id: self.u32(),
+                x: self.i32(),
+                y: self.i32(),
+            })
+            .collect()
+    }
+
+    fn room(&mut self) -> RoomInfo {
+        RoomInfo {
+            id: self.u32(),
+            name: self.string(),
+            players: self.u32(),
+            max_players: self.u32(),
+        }
+    }
+
+    fn client(&mut self) -> ClientMessage {
+        match self.below(12) {
+            0 => ClientMessage::Hello {
+                version: self.u32(),
+                name: self.string(),
+            },
+            1 => ClientMessage::Input(Input {
+                seq: self.u32(),
+                keys: Keys(self.next() as u8),
+            }),
+            2 => ClientMessage::Chat(self.string()),
+            3 => ClientMessage::Ping(self.u32()),
+            4 => ClientMessage::Pong(self.u32()),
+            5 => ClientMessage::ListRooms,
+            6 => ClientMessage::CreateRoom {
+                name: self.string(),
+                private: self.next().is_multiple_of(2),
+                max_players: self.u32(),
+            },
+            7 => ClientMessage::JoinRoom(self.u32()),
+            8 => ClientMessage::JoinCode(self.string()),
+            9 => ClientMessage::QuickMatch,
+            10 => ClientMessage::LeaveRoom,
+            _ => ClientMessage::Kick(self.u32()),
+        }
+    }
+
+    fn server(&mut self) -> ServerMessage {
+        match self.below(12) {
+            0 => ServerMessage::Welcome { id: self.u32() },
+            1 => ServerMessage::Snapshot(Snapshot {
+                tick: self.u32(),
+                ack: self.u32(),
+                entities: self.entities(),
+            }),
+            2 => ServerMessage::Delta(Delta {
+                tick: self.u32(),
+                base: self.u32(),
+                ack: self.u32(),
+                changed: self.entities(),
+                removed: (0..self.below(8)).map(|_| self.u32()).collect(),
+            }),
+            3 => ServerMessage::Chat {
+                from: self.u32(),
+                text: self.string(),
+            },
+            4 => ServerMessage::Error {
+                code: [
+                    ErrorCode::Version,
+                    ErrorCode::Malformed,
+                    ErrorCode::Unexpected,
+                    ErrorCode::NotFound,
+                    ErrorCode::Full,
+                    ErrorCode::Limit,
+                    ErrorCode::NotHost,
+                ][self.below(7) as usize],
+                message: self.string(),
+            },
+            5 => ServerMessage::Ping(self.u32()),
+            6 => ServerMessage::Pong(self.u32()),
+            7 => ServerMessage::Rooms((0..self.below(4)).map(|_| self.room()).collect()),
+            8 => ServerMessage::Joined {
+                room: self.room(),
+                code: (self.next().is_multiple_of(2)).then(|| self.string()),
+                host: self.u32(),
+                tick: self.u32(),
+            },
+            9 => ServerMessage::Left,
+            10 => ServerMessage::Host(self.u32()),
+            _ => ServerMessage::Kicked(self.u32()),
+        }
+    }
+}
+
+#[test]
+fn varints() {
+    for x in [0, 1, 127, 128, 300, u32::MAX] {
+        let mut w = Writer::default();
+        w.u32(x);
+        assert_eq!(Reader(&w.0).u32(), Ok(x));
+    }
+    for x in [0, -1, 1, -64, 64, i32::MIN, i32::MAX] {
+        let mut w = Writer::default();
+        w.i32(x);
+        assert_eq!(Reader(&w.0).i32(), Ok(x));
+    }
+    // Small magnitudes stay small regardless of sign
+    let mut w = Writer::default();
+    w.i32(-1);
+    assert_eq!(w.0, [1]);
+}
+
+#[test]
+fn varint_overflow() {
+    assert!(Reader(&[0xff, 0xff, 0xff, 0xff, 0x10]).u32().is_err());
+    assert!(Reader(&[0xff; 11]).u64().is_err());
+    assert!(Reader(&[0x80]).u32().is_err());
+    assert!(Reader(&[0x81, 0x00]).u32().is_err());
+}
+
+/// Wire format must not change without bumping [`VERSION`].
+#[test]
+fn golden() {
+    assert_eq!(VERSION, 4);
+    assert_eq!(
+        encode(&ClientMessage::Hello {
+            version: 2,
+            name: "ab".into()
+        }),
+        [0, 2, 2, b'a', b'b']
+    );
+    assert_eq!(
+        encode(&ClientMessage::Input(Input {
+            seq: 300,
+            keys: Keys::UP
+        })),
+        [1, 0xac, 0x02, 1]
+    );
+    assert_eq!(
+        encode(&ServerMessage::Snapshot(Snapshot {
+            tick: 1,
+            ack: 2,
+            entities: vec![Entity { id: 3, x: -1, y: 1 }],
+        })),
+        [1, 1, 2, 1, 3, 1, 2]
+    );
+    assert_eq!(
+        encode(&ServerMessage::Joined {
+            room: RoomInfo {
+                id: 1,
+                name: "r".into(),
+                players: 2,
+                max_players: 8
+            },
+            code: Some("AB".into()),
+            host: 5,
+            tick: 0,
+        }),
+        [8, 1, 1, b'r', 2, 8, 1, 2, b'A', b'B', 5, 0]
+    );
+    assert_eq!(encode(&ClientMessage::Kick(7)), [11, 7]);
+    assert_eq!(encode(&ServerMessage::Kicked(1)), [11, 1]);
+}
+
+#[test]
+fn round_trip() {
+    let mut rng = Rng(0x9e3779b97f4a7c15);
+    for _ in 0..10_000 {
+        let x = rng.client();
+        assert_eq!(decode::<ClientMessage>(&encode(&x)), Ok(x));
+        let x = rng.server();
+        assert_eq!(decode::<ServerMessage>(&encode(&x)), Ok(x));
+    }
+}
+
+#[test]
+fn truncated_and_trailing() {
+    let mut rng = Rng(1);
+    for _ in 0..1_000 {
+        let bytes = encode(&rng.server());
+        for len in 0..bytes.len() {
+            assert!(decode::<ServerMessage>(&bytes[..len]).is_err());
+        }
+        let mut bytes = bytes;
+        bytes.push(0);
+        assert!(decode::<ServerMessage>(&bytes).is_err());
+    }
+}
+
+/// Garbage and mutated frames must be rejected or decoded, never panic.
+#[test]
+fn fuzz() {
+    let mut rng = Rng(0xdeadbeef);
+    for _ in 0..100_000 {
+        let bytes: Vec<u8> = (0..rng.below(32)).map(|_| rng.next() as u8).collect();
+        let _ = decode::<ClientMessage>(&bytes);
+        let _ = decode::<ServerMessage>(&bytes);
+    }
+    for _ in 0..10_000 {
+        let mut bytes = encode(&rng.server());
+        let i = rng.below(bytes.len() as u64) as usize;
+        bytes[i] ^= 1 << rng.below(8);
+        if let Ok(x) = decode::<ServerMessage>(&bytes) {
+            // Whatever it decoded to must encode back to the same bytes.
+            assert_eq!(encode(&x), bytes);
+        }
+    }
+}
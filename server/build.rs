@@ -5,12 +5,28 @@ use std::{
     path::Path,
 };
 
+use build_support::{compress, hash};
+
 const EXT2MIME: &[(&str, &str)] = &[
     ("html", "text/html"),
     ("js", "text/javascript"),
     ("module.wasm", "application/wasm"),
 ];
 
+/// `Some(include_bytes!(..))` for every compressed variant of `source` that is smaller than it.
+fn compressed(source: &Path, dir: &Path) -> [String; 2] {
+    let bytes = fs::read(source).unwrap();
+    let name = source.file_name().unwrap().to_string_lossy();
+    compress(&bytes).map(|(ext, x)| match x {
+        Some(x) => {
+            let dest = dir.join(format!("{name}.{ext}"));
+            fs::write(&dest, x).unwrap();
+            format!("Some(include_bytes!({:?}))", dest.to_string_lossy())
+        }
+        None => "None".to_string(),
+    })
+}
+
 fn main() {
     let out_dir = env::var_os("OUT_DIR").unwrap();
     let dest_path = Path::new(&out_dir).join("client_files.rs");
@@ -21,9 +37,22 @@ fn main() {
         .open(dest_path)
         .unwrap();
 
-    file.write_all(b"{").unwrap();
+    let compressed_dir = Path::new(&out_dir).join("compressed");
+    fs::create_dir_all(&compressed_dir).unwrap();
 
-    for ent in fs::read_dir("../web/dist/").unwrap().map(|x| x.unwrap()) {
+    // &[Route { path: "/..", cache: .., file: File { .. } }, ..] (sorted by path, empty without
+    // `embed-client`)
+
+    let dist = env::var_os("CARGO_FEATURE_EMBED_CLIENT").map(|_| {
+        fs::read_dir("../web/dist/").unwrap_or_else(|why| {
+            panic!(
+                "Can't read ../web/dist ({why}). Build the web client first or disable the \
+                 `embed-client` feature"
+            )
+        })
+    });
+    let mut routes = vec![];
+    for ent in dist.into_iter().flatten().map(|x| x.unwrap()) {
         let filename_os = ent.file_name();
         let filename = filename_os.to_string_lossy();
         let ext = filename.split_once('.').unwrap().1;
@@ -33,25 +62,29 @@ fn main() {
             .map(|x| x.1)
             .unwrap_or_else(|| panic!("Extension '{ext}' is not supported"));
 
-        file.write_all(b"if req.uri().path()==\"/client/").unwrap();
-        file.write_all(filename.as_bytes()).unwrap();
-        file.write_all(b"\"{return Ok(Response::builder().header(\"Content-Type\",\"")
-            .unwrap();
-        file.write_all(mime.as_bytes()).unwrap();
-        file.write_all(b"\").body(Full::new(Bytes::from_static(include_bytes!(")
-            .unwrap();
-        file.write_all(
-            format!(
-                "{:?}",
-                current_dir().unwrap().join(ent.path()).to_string_lossy()
-            )
-            .as_bytes(),
-        )
-        .unwrap();
-        file.write_all(b")))).unwrap());}\n").unwrap();
+        let source = current_dir().unwrap().join(ent.path());
+        let [gzip, br] = compressed(&source, &compressed_dir);
+        let etag = hash(&fs::read(&source).unwrap());
+        // Webpack names wasm modules after their contents.
+        let cache = match ext {
+            "module.wasm" => "IMMUTABLE",
+            _ => "REVALIDATE",
+        };
+
+        let path = format!("/{filename}");
+        let route = format!(
+            "Route{{path:{path:?},cache:files::{cache},file:File{{bytes:include_bytes!({:?}),mime:{mime:?},gzip:{gzip},br:{br},etag:{etag:?}}}}},",
+            source.to_string_lossy(),
+        );
+        routes.push((path, route));
     }
+    routes.sort();
 
-    file.write_all(b"}").unwrap();
+    file.write_all(b"&[").unwrap();
+    for (_, x) in routes {
+        file.write_all(x.as_bytes()).unwrap();
+    }
+    file.write_all(b"]").unwrap();
 
     println!("cargo::rerun-if-changed=../web/src/");
     println!("cargo::rerun-if-changed=../web/dist/");
@@ -0,0 +1,103 @@
+//! Per-request logs on stdout, see [`AccessLog`].
+
+use std::{
+    io::Write,
+    net::IpAddr,
+    time::{Duration, SystemTime},
+};
+
+use hyper::{
+    body::Body,
+    header::{HeaderName, REFERER, USER_AGENT},
+    Request, Response,
+};
+use serde_json::json;
+
+use crate::config::AccessLog;
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// UTC year, month (1-12), day, hour, minute and second.
+fn utc(time: SystemTime) -> (i64, u32, u32, u64, u64, u64) {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // Days to civil date, from http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day, secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Header value with quotes, backslashes and anything unprintable escaped.
+fn header(req: &Request<impl Body>, name: HeaderName) -> Option<String> {
+    let x = req.headers().get(name)?;
+    Some(
+        String::from_utf8_lossy(x.as_bytes())
+            .escape_default()
+            .to_string(),
+    )
+}
+
+/// Log a request handled in `elapsed`, `bytes` being the size of the response body.
+pub fn log<B>(
+    format: AccessLog,
+    client: Option<IpAddr>,
+    req: &Request<impl Body>,
+    res: &Response<B>,
+    bytes: u64,
+    elapsed: Duration,
+) {
+    let path = req.uri().path_and_query().map_or("/", |x| x.as_str());
+    let ms = elapsed.as_secs_f64() * 1000.0;
+    let (year, month, day, hour, minute, second) = utc(SystemTime::now());
+    let line = match format {
+        AccessLog::Off => return,
+        AccessLog::Combined => {
+            let or = |x: Option<String>| x.unwrap_or_else(|| "-".to_owned());
+            format!(
+                "{} - - [{day:02}/{}/{year}:{hour:02}:{minute:02}:{second:02} +0000] \"{} {} {:?}\" {} {} \"{}\" \"{}\" {ms:.3}",
+                or(client.map(|x| x.to_string())),
+                MONTHS[month as usize - 1],
+                req.method(),
+                path.escape_default(),
+                req.version(),
+                res.status().as_u16(),
+                if bytes == 0 { "-".to_owned() } else { bytes.to_string() },
+                or(header(req, REFERER)),
+                or(header(req, USER_AGENT)),
+            )
+        }
+        AccessLog::Json => {
+            let value = |name| {
+                req.headers()
+                    .get(name)
+                    .map(|x| String::from_utf8_lossy(x.as_bytes()).into_owned())
+            };
+            json!({
+                "time": format!("{year}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z"),
+                "client": client,
+                "method": req.method().as_str(),
+                "path": path,
+                "version": format!("{:?}", req.version()),
+                "status": res.status().as_u16(),
+                "bytes": bytes,
+                "ms": (ms * 1000.0).round() / 1000.0,
+                "referer": value(REFERER),
+                "user_agent": value(USER_AGENT),
+            })
+            .to_string()
+        }
+    };
+    let _ = writeln!(std::io::stdout().lock(), "{line}");
+}
@@ -1,14 +1,775 @@
-use std::net::SocketAddr;
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsString,
+    fmt::{self, Display},
+    fs,
+    marker::PhantomData,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::{Path, PathBuf},
+};
 
 use serde::Deserialize;
+use toml::{Table, Value};
+
+/// Prefix of environment variables overriding config keys, see [`Config::load`].
+const ENV_PREFIX: &str = "USMG_";
+
+fn yes() -> bool {
+    true
+}
+
+/// A finite number above zero. Rates of zero would never refill.
+fn positive<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let x = f64::deserialize(deserializer)?;
+    match x > 0.0 && x.is_finite() {
+        true => Ok(x),
+        false => Err(serde::de::Error::custom(format!(
+            "expected a positive number, found {x}"
+        ))),
+    }
+}
+
+/// A number above zero.
+fn nonzero<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    match u32::deserialize(deserializer)? {
+        0 => Err(serde::de::Error::custom("expected at least 1, found 0")),
+        x => Ok(x),
+    }
+}
+
+/// Where a listener accepts connections, one of `bind`, `unix` or `systemd`.
+#[derive(Clone)]
+pub enum Bind {
+    /// TCP address.
+    Tcp(SocketAddr),
+    /// Unix domain socket path. A stale socket file is replaced.
+    Unix(PathBuf),
+    /// Socket passed by systemd socket activation, by its `FileDescriptorName=`.
+    Systemd(String),
+}
+impl Display for Bind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(x) => write!(f, "{x}"),
+            Self::Unix(x) => write!(f, "unix:{}", x.display()),
+            Self::Systemd(x) => write!(f, "systemd:{x}"),
+        }
+    }
+}
+
+/// Address range like `10.0.0.0/8`, or a single address.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(try_from = "String")]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u32,
+}
+impl Cidr {
+    fn bits(addr: IpAddr) -> (u128, u32) {
+        match addr {
+            IpAddr::V4(x) => (x.to_bits().into(), 32),
+            IpAddr::V6(x) => match x.to_ipv4_mapped() {
+                Some(x) => (x.to_bits().into(), 32),
+                None => (x.to_bits(), 128),
+            },
+        }
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let ((a, a_len), (b, b_len)) = (Self::bits(self.addr), Self::bits(addr));
+        let shift = a_len - self.prefix;
+        a_len == b_len && a.checked_shr(shift).unwrap_or(0) == b.checked_shr(shift).unwrap_or(0)
+    }
+}
+impl TryFrom<String> for Cidr {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let error = || format!("invalid address range '{value}'");
+        let (addr, prefix) = match value.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value.as_str(), None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| error())?;
+        let len = Self::bits(addr).1;
+        let prefix = match prefix {
+            None => len,
+            Some(x) => x.parse().ok().filter(|x| *x <= len).ok_or_else(error)?,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+/// How a listener finds out who clients are when behind a reverse proxy.
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Proxy {
+    /// Clients connect directly.
+    #[default]
+    None,
+    /// From `Forwarded` or `X-Forwarded-For` headers.
+    Headers,
+    /// From a HAProxy PROXY protocol (v1 or v2) header every connection starts with.
+    Protocol,
+}
+
+fn loopback() -> Vec<Cidr> {
+    vec![
+        Cidr {
+            addr: Ipv4Addr::LOCALHOST.into(),
+            prefix: 32,
+        },
+        Cidr {
+            addr: Ipv6Addr::LOCALHOST.into(),
+            prefix: 128,
+        },
+    ]
+}
+
+pub struct Listen {
+    pub bind: Bind,
+    /// Permissions of a `unix` socket file, e.g. `0o660`.
+    pub mode: Option<u32>,
+    pub proxy: Proxy,
+    /// Peers believed to be proxies, loopback by default. Unix socket peers always are.
+    pub trusted: Vec<Cidr>,
+}
+impl Listen {
+    fn new(
+        (bind, unix, systemd): (Option<SocketAddr>, Option<PathBuf>, Option<String>),
+        mode: Option<u32>,
+        proxy: Proxy,
+        trusted: Vec<Cidr>,
+    ) -> Result<Self, String> {
+        let bind = match (bind, unix, systemd) {
+            (Some(x), None, None) => Bind::Tcp(x),
+            (None, Some(x), None) => Bind::Unix(x),
+            (None, None, Some(x)) => Bind::Systemd(x),
+            _ => return Err("expected exactly one of `bind`, `unix` and `systemd`".into()),
+        };
+        if mode.is_some() && !matches!(bind, Bind::Unix(_)) {
+            return Err("`mode` only applies to `unix` sockets".into());
+        }
+        Ok(Self {
+            bind,
+            mode,
+            proxy,
+            trusted,
+        })
+    }
+}
 
-#[derive(Deserialize)]
 pub struct Http {
-    pub bind: SocketAddr,
+    pub listen: Listen,
+    /// Accept HTTP/2 with prior knowledge (h2c) besides HTTP/1.
+    pub http2: bool,
+    /// Redirect every request to this origin (e.g. `https://example.com`) instead of serving it.
+    pub redirect: Option<String>,
+}
+
+pub struct Https {
+    pub listen: Listen,
+    /// Offer HTTP/2 through ALPN besides HTTP/1.
+    pub http2: bool,
+    /// PEM certificate chain, leaf first.
+    pub cert: PathBuf,
+    /// PEM private key.
+    pub key: PathBuf,
+}
+
+/// Deserialize `Raw` and convert it while still inside the table, so conversion errors point at
+/// the table instead of whatever contains it.
+fn converted<'de, D, Raw, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    Raw: Deserialize<'de>,
+    T: TryFrom<Raw, Error = String>,
+{
+    struct Visitor<Raw, T>(PhantomData<(Raw, T)>);
+    impl<'de, Raw: Deserialize<'de>, T: TryFrom<Raw, Error = String>> serde::de::Visitor<'de>
+        for Visitor<Raw, T>
+    {
+        type Value = T;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a table")
+        }
+
+        fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<T, A::Error> {
+            let raw = Raw::deserialize(serde::de::value::MapAccessDeserializer::new(map))?;
+            T::try_from(raw).map_err(serde::de::Error::custom)
+        }
+    }
+    deserializer.deserialize_map(Visitor(PhantomData))
+}
+
+/// [`Http`] as written. Listener keys aren't flattened into a [`Listen`], since errors in
+/// flattened tables lose their location.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawHttp {
+    bind: Option<SocketAddr>,
+    unix: Option<PathBuf>,
+    systemd: Option<String>,
+    mode: Option<u32>,
+    #[serde(default)]
+    proxy: Proxy,
+    #[serde(default = "loopback")]
+    trusted: Vec<Cidr>,
+    #[serde(default = "yes")]
+    http2: bool,
+    redirect: Option<String>,
+}
+impl<'de> Deserialize<'de> for Http {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        converted::<_, RawHttp, _>(deserializer)
+    }
+}
+impl TryFrom<RawHttp> for Http {
+    type Error = String;
+
+    fn try_from(x: RawHttp) -> Result<Self, Self::Error> {
+        Ok(Self {
+            listen: Listen::new((x.bind, x.unix, x.systemd), x.mode, x.proxy, x.trusted)?,
+            http2: x.http2,
+            redirect: x.redirect,
+        })
+    }
+}
+
+/// [`Https`] as written, see [`RawHttp`].
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawHttps {
+    bind: Option<SocketAddr>,
+    unix: Option<PathBuf>,
+    systemd: Option<String>,
+    mode: Option<u32>,
+    #[serde(default)]
+    proxy: Proxy,
+    #[serde(default = "loopback")]
+    trusted: Vec<Cidr>,
+    #[serde(default = "yes")]
+    http2: bool,
+    cert: PathBuf,
+    key: PathBuf,
+}
+impl<'de> Deserialize<'de> for Https {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        converted::<_, RawHttps, _>(deserializer)
+    }
+}
+impl TryFrom<RawHttps> for Https {
+    type Error = String;
+
+    fn try_from(x: RawHttps) -> Result<Self, Self::Error> {
+        Ok(Self {
+            listen: Listen::new((x.bind, x.unix, x.systemd), x.mode, x.proxy, x.trusted)?,
+            http2: x.http2,
+            cert: x.cert,
+            key: x.key,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rooms {
+    /// Most rooms existing at once.
+    pub max_rooms: usize,
+    /// Default and upper limit of players per room.
+    #[serde(deserialize_with = "nonzero")]
+    pub max_players: u32,
+    /// Seconds an empty room is kept around.
+    pub idle_timeout: u64,
+}
+impl Default for Rooms {
+    fn default() -> Self {
+        Self {
+            max_rooms: 64,
+            max_players: 8,
+            idle_timeout: 300,
+        }
+    }
+}
+
+/// Token bucket: `burst` at once, refilled at `per_second`.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct Rate {
+    #[serde(deserialize_with = "positive")]
+    pub per_second: f64,
+    pub burst: u32,
+}
+
+/// Limits per client IP, or per /64 for IPv6. Clients on unix sockets aren't limited.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// HTTP requests, answered with 429 beyond it.
+    pub requests: Rate,
+    /// Messages per WebSocket session, closed beyond it.
+    pub messages: Rate,
+    /// Connections open at once, including WebSockets. Extra ones are closed right away.
+    pub max_connections: u32,
+}
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            requests: Rate {
+                per_second: 20.0,
+                burst: 60,
+            },
+            // Inputs alone are sent at the tick rate.
+            messages: Rate {
+                per_second: 60.0,
+                burst: 120,
+            },
+            max_connections: 16,
+        }
+    }
+}
+
+/// Response headers set on top of the defaults, by route group. An empty value removes one.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Headers {
+    /// Every response.
+    pub all: BTreeMap<String, String>,
+    /// Main and error pages.
+    pub pages: BTreeMap<String, String>,
+    /// Web client, under `/play` and `/client`.
+    pub client: BTreeMap<String, String>,
+    /// Game assets, under `/assets`.
+    pub assets: BTreeMap<String, String>,
+}
+
+/// Simulated network conditions, for testing on localhost. Off unless something is set.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct Netsim {
+    /// Milliseconds every message to clients and every input from them is delayed by.
+    pub latency: u64,
+    /// Up to this many milliseconds are randomly added to `latency`.
+    pub jitter: u64,
+    /// Fraction of snapshots, deltas and inputs dropped, from 0 to 1.
+    pub loss: f64,
+}
+impl Netsim {
+    pub fn enabled(&self) -> bool {
+        self.latency > 0 || self.jitter > 0 || self.loss > 0.0
+    }
+}
+
+/// Serve files from disk instead of the embedded copies, so client changes show up without
+/// rebuilding the server. Nothing is cached.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Dev {
+    pub enabled: bool,
+    /// Built web client. `web/dist` of the source tree by default.
+    pub client: Option<PathBuf>,
+}
+
+/// Format of per-request logs on stdout.
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLog {
+    #[default]
+    Off,
+    /// Apache/nginx combined format, followed by the latency in milliseconds.
+    Combined,
+    /// One JSON object per line.
+    Json,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Log {
+    pub access: AccessLog,
+}
+
+/// Prometheus metrics. Anyone who can reach them can read them.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Metrics {
+    /// Path they're served on, e.g. `/metrics`. Off unless set.
+    pub path: Option<String>,
 }
 
 #[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     #[serde(default = "Default::default")]
     pub http: Vec<Http>,
+    #[serde(default = "Default::default")]
+    pub https: Vec<Https>,
+    #[serde(default = "Default::default")]
+    pub rooms: Rooms,
+    #[serde(default = "Default::default")]
+    pub limits: Limits,
+    #[serde(default = "Default::default")]
+    pub netsim: Netsim,
+    #[serde(default = "Default::default")]
+    pub dev: Dev,
+    #[serde(default = "Default::default")]
+    pub headers: Headers,
+    #[serde(default = "Default::default")]
+    pub log: Log,
+    #[serde(default = "Default::default")]
+    pub metrics: Metrics,
+}
+impl Config {
+    /// Read `path`, with keys overridden by `USMG_` environment variables. Nested keys are
+    /// separated by `__` and items of arrays numbered, e.g. `USMG_ROOMS__MAX_PLAYERS=4` or
+    /// `USMG_HTTP__0__BIND=0.0.0.0:80`. Values are parsed as TOML, or taken as strings if they
+    /// aren't valid TOML.
+    ///
+    /// Errors in the file point at its line and column, errors an override causes name its
+    /// variable.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|why| format!("{}: {why}", path.display()))?;
+        Self::parse(path, &text, env::vars_os())
+    }
+
+    /// [`Config::load`] of `text` read from `path`, with overrides from `vars`.
+    fn parse(
+        path: &Path,
+        text: &str,
+        vars: impl IntoIterator<Item = (OsString, OsString)>,
+    ) -> Result<Self, String> {
+        let located = |why: toml::de::Error| match why.span() {
+            Some(x) => {
+                let before = &text[..x.start];
+                let line = before.matches('\n').count() + 1;
+                let column = before.chars().rev().take_while(|&x| x != '\n').count() + 1;
+                format!("{}:{line}:{column}: {}", path.display(), why.message())
+            }
+            None => format!("{}: {}", path.display(), why.message()),
+        };
+        let table: Table = toml::from_str(text).map_err(located)?;
+        let mut config = toml::from_str(text).map_err(located);
+        let mut value = Value::Table(table);
+        for (name, keys, new) in overrides(vars)? {
+            set(&mut value, &keys, new).map_err(|why| format!("{name}: {why}"))?;
+            // Values carry no location, so the first variable that breaks a working config is
+            // blamed. A broken file is reported as is, unless the overrides fix it.
+            config = match (config, value.clone().try_into()) {
+                (_, Ok(x)) => Ok(x),
+                (Ok(_), Err(why)) => return Err(format!("{name}: {}", why.message())),
+                (Err(why), Err(_)) => Err(why),
+            };
+        }
+        config
+    }
+}
+
+/// Overrides among environment variables `vars`: each one's name, keys and value.
+///
+/// Every key is in a section, so variables without `__` (like the desktop client's
+/// `USMG_SERVER`) are left alone.
+fn overrides(
+    vars: impl IntoIterator<Item = (OsString, OsString)>,
+) -> Result<Vec<(String, Vec<String>, Value)>, String> {
+    let mut vars: Vec<_> = vars
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value)))
+        .filter(|(name, _)| name.starts_with(ENV_PREFIX) && name.contains("__"))
+        .collect();
+    vars.sort();
+    vars.into_iter()
+        .map(|(name, value)| {
+            let value = value
+                .to_str()
+                .ok_or_else(|| format!("{name} isn't valid unicode"))?;
+            let keys = name[ENV_PREFIX.len()..]
+                .split("__")
+                .map(|x| x.to_lowercase())
+                .collect();
+            let value = parse(value);
+            Ok((name, keys, value))
+        })
+        .collect()
+}
+
+/// `value` as TOML, or as a string if it isn't valid TOML.
+fn parse(value: &str) -> Value {
+    toml::from_str::<Table>(&format!("x = {value}"))
+        .ok()
+        .and_then(|mut x| x.remove("x"))
+        .unwrap_or_else(|| Value::String(value.to_owned()))
+}
+
+/// Set the item at `keys` in `root`, creating the tables and arrays on the way.
+fn set(root: &mut Value, keys: &[String], new: Value) -> Result<(), String> {
+    let mut value = root;
+    for (i, key) in keys.iter().enumerate() {
+        // Numbered keys are indices, so what they're in is an array.
+        let empty = || match keys.get(i + 1) {
+            Some(x) if x.parse::<usize>().is_ok() => Value::Array(vec![]),
+            _ => Value::Table(Table::new()),
+        };
+        value = match value {
+            Value::Table(x) => x.entry(key.clone()).or_insert_with(empty),
+            Value::Array(x) => {
+                let index: usize = key
+                    .parse()
+                    .map_err(|_| format!("'{key}' isn't an index into an array"))?;
+                if index == x.len() {
+                    x.push(empty());
+                }
+                x.get_mut(index)
+                    .ok_or_else(|| format!("there's no item {index} to override"))?
+            }
+            _ => return Err(format!("'{key}' is inside something that isn't a table")),
+        };
+    }
+    *value = new;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(text: &str, vars: &[(&str, &str)]) -> Result<Config, String> {
+        let vars = vars.iter().map(|(x, y)| (x.into(), y.into()));
+        Config::parse(Path::new("c.toml"), text, vars)
+    }
+
+    fn error(text: &str, vars: &[(&str, &str)]) -> String {
+        load(text, vars).err().unwrap()
+    }
+
+    #[test]
+    fn locations() {
+        assert_eq!(
+            error("[[http]]\nbind = \"127.0.0.1:x\"", &[]),
+            "c.toml:2:8: invalid socket address syntax"
+        );
+        assert_eq!(
+            error("[rooms]\nmax_players = 0", &[]),
+            "c.toml:2:15: expected at least 1, found 0"
+        );
+        assert_eq!(
+            error("[rooms]\nmax_rooms = 1\n[rooms]", &[]),
+            "c.toml:3:1: invalid table header\nduplicate key `rooms` in document root"
+        );
+        let listeners =
+            "[[http]]\nbind = \"127.0.0.1:80\"\n\n[[http]]\nunix = \"/x\"\nsystemd = \"x\"";
+        assert_eq!(
+            error(listeners, &[]),
+            "c.toml:4:1: expected exactly one of `bind`, `unix` and `systemd`"
+        );
+        assert_eq!(
+            error(
+                "[[https]]\nbind = \"127.0.0.1:80\"\nkey = \"k\"\ncert = \"c\"\nmode = 0o600",
+                &[]
+            ),
+            "c.toml:1:1: `mode` only applies to `unix` sockets"
+        );
+    }
+
+    #[test]
+    fn unknown_keys() {
+        assert!(error("[rooms]\nmax_player = 4", &[])
+            .starts_with("c.toml:2:1: unknown field `max_player`, expected one of"));
+        assert!(
+            error("[[http]]\nbind = \"127.0.0.1:80\"\nhttp3 = true", &[])
+                .starts_with("c.toml:3:1: unknown field `http3`")
+        );
+        assert!(error("[[https]]\nredirect = \"x\"", &[])
+            .starts_with("c.toml:2:1: unknown field `redirect`"));
+        assert!(
+            error("[limits.requests]\nper_second = 1\nburst = 1\nmax = 2", &[])
+                .starts_with("c.toml:4:1: unknown field `max`")
+        );
+        assert!(error("room = 1", &[]).starts_with("c.toml:1:1: unknown field `room`"));
+        // Header names are up to the user.
+        assert!(load("[headers.all]\nx-anything = \"1\"", &[]).is_ok());
+    }
+
+    #[test]
+    fn env_overrides() {
+        let config = load(
+            "[rooms]\nmax_players = 4",
+            &[
+                ("USMG_ROOMS__MAX_PLAYERS", "2"),
+                ("USMG_HTTP__0__BIND", "127.0.0.1:80"),
+                ("USMG_HTTP__0__HTTP2", "false"),
+                ("USMG_NETSIM__LOSS", "0.5"),
+                ("USMG_SERVER", "ignored"),
+                ("OTHER__X", "ignored"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.rooms.max_players, 2);
+        assert_eq!(config.http.len(), 1);
+        assert_eq!(config.http[0].listen.bind.to_string(), "127.0.0.1:80");
+        assert!(!config.http[0].http2);
+        assert_eq!(config.netsim.loss, 0.5);
+    }
+
+    #[test]
+    fn env_errors() {
+        let file = "[rooms]\nmax_players = 4";
+        assert_eq!(
+            error(file, &[("USMG_ROOMS__MAX_PLAYERS", "0")]),
+            "USMG_ROOMS__MAX_PLAYERS: expected at least 1, found 0"
+        );
+        assert!(error(file, &[("USMG_ROOMS__MAX_PLAYER", "3")])
+            .starts_with("USMG_ROOMS__MAX_PLAYER: unknown field `max_player`"));
+        assert_eq!(
+            error(
+                file,
+                &[
+                    ("USMG_HTTP__0__BIND", "nope"),
+                    ("USMG_ROOMS__IDLE_TIMEOUT", "1")
+                ]
+            ),
+            "USMG_HTTP__0__BIND: invalid socket address syntax"
+        );
+        assert_eq!(
+            error(file, &[("USMG_ROOMS__MAX_PLAYERS__X", "1")]),
+            "USMG_ROOMS__MAX_PLAYERS__X: 'x' is inside something that isn't a table"
+        );
+        assert_eq!(
+            error(file, &[("USMG_HTTP__1__BIND", "127.0.0.1:80")]),
+            "USMG_HTTP__1__BIND: there's no item 1 to override"
+        );
+    }
+
+    #[test]
+    fn env_and_broken_file() {
+        // Overrides can fix the file.
+        let config = load(
+            "[rooms]\nmax_players = 0",
+            &[("USMG_ROOMS__MAX_PLAYERS", "3")],
+        );
+        assert_eq!(config.unwrap().rooms.max_players, 3);
+        // Otherwise its errors keep their location.
+        assert_eq!(
+            error(
+                "[rooms]\nmax_players = 0",
+                &[("USMG_ROOMS__IDLE_TIMEOUT", "1")]
+            ),
+            "c.toml:2:15: expected at least 1, found 0"
+        );
+    }
+
+    #[test]
+    fn env_values() {
+        assert_eq!(parse("4"), Value::Integer(4));
+        assert_eq!(parse("0.5"), Value::Float(0.5));
+        assert_eq!(parse("true"), Value::Boolean(true));
+        assert_eq!(parse("\"x y\""), Value::String("x y".into()));
+        assert_eq!(
+            parse("[1, \"a\"]"),
+            Value::Array(vec![Value::Integer(1), Value::String("a".into())])
+        );
+        // Anything that isn't TOML is a string.
+        for x in ["127.0.0.1:80", "/tmp/x.sock", "x = 1", "", "[1", "\"a"] {
+            assert_eq!(parse(x), Value::String(x.into()));
+        }
+    }
+
+    #[test]
+    fn set_keys() {
+        let keys = |x: &str| x.split('.').map(String::from).collect::<Vec<_>>();
+        let mut root = Value::Table(Table::new());
+        set(&mut root, &keys("a.b"), Value::Integer(1)).unwrap();
+        set(&mut root, &keys("a.c"), Value::Integer(2)).unwrap();
+        set(&mut root, &keys("list.0.x"), Value::Integer(3)).unwrap();
+        set(&mut root, &keys("list.1.x"), Value::Integer(4)).unwrap();
+        set(&mut root, &keys("list.0.y"), Value::Integer(5)).unwrap();
+        set(&mut root, &keys("a.b"), Value::Integer(6)).unwrap();
+        assert_eq!(
+            root.to_string(),
+            "{ list = [{ x = 3, y = 5 }, { x = 4 }], a = { b = 6, c = 2 } }"
+        );
+
+        assert_eq!(
+            set(&mut root, &keys("list.3.x"), Value::Integer(1)),
+            Err("there's no item 3 to override".into())
+        );
+        assert_eq!(
+            set(&mut root, &keys("list.x"), Value::Integer(1)),
+            Err("'x' isn't an index into an array".into())
+        );
+        assert_eq!(
+            set(&mut root, &keys("a.b.c"), Value::Integer(1)),
+            Err("'c' is inside something that isn't a table".into())
+        );
+    }
+
+    #[test]
+    fn rates() {
+        let limits = |rate: &str| {
+            toml::from_str::<Limits>(&format!("requests = {{ per_second = {rate}, burst = 1 }}"))
+                .map(|x| x.requests.per_second)
+                .map_err(|x| x.message().to_owned())
+        };
+        assert_eq!(limits("5"), Ok(5.0));
+        assert_eq!(limits("0.5"), Ok(0.5));
+        assert_eq!(
+            limits("0"),
+            Err("expected a positive number, found 0".to_owned())
+        );
+        assert!(limits("-1").is_err());
+        assert!(limits("nan").is_err());
+        assert!(limits("inf").is_err());
+    }
+
+    #[test]
+    fn max_players() {
+        let rooms = |x: &str| toml::from_str::<Rooms>(x).map(|x| x.max_players);
+        assert_eq!(rooms("").unwrap(), 8);
+        assert_eq!(rooms("max_players = 1").unwrap(), 1);
+        let why = rooms("max_players = 0").unwrap_err();
+        assert_eq!(why.message(), "expected at least 1, found 0");
+    }
+
+    #[test]
+    fn cidr() {
+        let contains = |range: &str, addr: &str| {
+            Cidr::try_from(range.to_owned())
+                .unwrap()
+                .contains(addr.parse().unwrap())
+        };
+        assert!(contains("0.0.0.0/0", "192.0.2.1"));
+        assert!(!contains("0.0.0.0/0", "2001:db8::1"));
+        assert!(contains("::/0", "2001:db8::1"));
+        assert!(!contains("::/0", "192.0.2.1"));
+        assert!(contains("10.0.0.0/8", "10.255.0.1"));
+        assert!(!contains("10.0.0.0/8", "11.0.0.1"));
+        assert!(contains("192.0.2.1/32", "192.0.2.1"));
+        assert!(!contains("192.0.2.1/32", "192.0.2.2"));
+        assert!(contains("192.0.2.1", "192.0.2.1"));
+        assert!(contains("2001:db8::1/128", "2001:db8::1"));
+        assert!(!contains("2001:db8::1/128", "2001:db8::2"));
+        assert!(contains("2001:db8::/32", "2001:db8:ffff::1"));
+        assert!(!contains("2001:db8::/32", "2001:db9::1"));
+        // IPv4-mapped addresses are IPv4 addresses, on either side.
+        assert!(contains("10.0.0.0/8", "::ffff:10.0.0.1"));
+        assert!(contains("::ffff:127.0.0.1", "127.0.0.1"));
+        assert!(!contains("::/0", "::ffff:127.0.0.1"));
+    }
+
+    #[test]
+    fn invalid_cidr() {
+        for x in [
+            "10.0.0.0/33",
+            "::/129",
+            "::ffff:10.0.0.0/104",
+            "10.0.0.0/",
+            "x",
+            "10.0.0.0/-1",
+        ] {
+            assert_eq!(
+                Cidr::try_from(x.to_owned()).err(),
+                Some(format!("invalid address range '{x}'"))
+            );
+        }
+    }
 }
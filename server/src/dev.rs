@@ -0,0 +1,91 @@
+//! Serving files straight from the source tree, see [`Dev`](crate::config::Dev).
+//!
+//! Files are read on every request. Fine for development, not for anything else.
+
+use std::path::Path;
+
+use assets::MANIFEST;
+use http_body_util::Full;
+use hyper::{
+    body::Bytes,
+    header::{CACHE_CONTROL, CONTENT_TYPE},
+    Response,
+};
+
+/// Built web client in the source tree.
+pub const CLIENT_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../web/dist");
+
+fn mime(path: &str) -> &'static str {
+    match extension(path).unwrap_or_default() {
+        "html" => "text/html",
+        "js" => "text/javascript",
+        "wasm" => "application/wasm",
+        "css" => "text/css",
+        "json" | "map" => "application/json",
+        "png" => "image/png",
+        "ttf" => "font/ttf",
+        "toml" => "application/toml",
+        _ => "application/octet-stream",
+    }
+}
+
+fn respond(bytes: Vec<u8>, mime: &str) -> Response<Full<Bytes>> {
+    Response::builder()
+        .header(CONTENT_TYPE, mime)
+        .header(CACHE_CONTROL, "no-cache")
+        .body(Full::new(Bytes::from(bytes)))
+        .unwrap()
+}
+
+/// Whether `path` is absolute and stays inside whatever directory it's looked up in.
+fn is_safe(path: &str) -> bool {
+    path.strip_prefix('/').is_some_and(|x| {
+        x.split('/')
+            .all(|x| !x.is_empty() && x != "." && x != ".." && !x.contains('\\'))
+    })
+}
+
+fn extension(path: &str) -> Option<&str> {
+    path.rsplit_once('.')
+        .map(|x| x.1)
+        .filter(|x| !x.contains('/'))
+}
+
+/// Whether `path` has the extension of some built asset. Keeps the build script, translations
+/// and whatever else lives next to the assets private.
+fn is_asset(path: &str) -> bool {
+    extension(path).is_some_and(|ext| MANIFEST.iter().any(|x| extension(x.path) == Some(ext)))
+}
+
+/// Web client file at `path` (e.g. `/index.html`) in `dir`.
+pub async fn client(dir: &Path, path: &str) -> Option<Response<Full<Bytes>>> {
+    if !is_safe(path) {
+        return None;
+    }
+    let bytes = tokio::fs::read(dir.join(&path[1..])).await.ok()?;
+    Some(respond(bytes, mime(path)))
+}
+
+/// Asset at `path`, converted from its source like at build time. Hashes in paths are ignored.
+pub async fn asset(path: &str) -> Option<Response<Full<Bytes>>> {
+    if !is_safe(path) || !is_asset(path) {
+        return None;
+    }
+    // `/<hash>/<path>` is the same file as `/<path>`.
+    let unhashed = path[1..].find('/').map(|x| path[x + 1..].to_owned());
+    let paths = [Some(path.to_owned()), unhashed];
+    // Conversion can take a while, so it runs off the worker like the reads.
+    let (bytes, path) = tokio::task::spawn_blocking(move || {
+        paths
+            .into_iter()
+            .flatten()
+            .find_map(|x| Some((assets::read_source(&x).ok()?, x)))
+    })
+    .await
+    .ok()??;
+    let mime = MANIFEST
+        .iter()
+        .find(|x| x.path == path)
+        .map_or_else(|| mime(&path), |x| x.mime);
+    Some(respond(bytes, mime))
+}
@@ -0,0 +1,373 @@
+use std::ops::Range;
+
+use http_body_util::Full;
+use hyper::{
+    body::{Body, Bytes},
+    header::{
+        AsHeaderName, ACCEPT_ENCODING, ACCEPT_RANGES, ALLOW, CACHE_CONTROL, CONTENT_ENCODING,
+        CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_NONE_MATCH, IF_RANGE, RANGE, VARY,
+    },
+    Method, Request, Response, StatusCode,
+};
+
+/// `Cache-Control` of files that may change on the same URL. ETags make revalidation cheap.
+pub const REVALIDATE: &str = "no-cache";
+/// `Cache-Control` of files whose URL changes with their contents.
+pub const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// An embedded file with its precompressed variants.
+pub struct File {
+    pub bytes: &'static [u8],
+    pub mime: &'static str,
+    pub gzip: Option<&'static [u8]>,
+    pub br: Option<&'static [u8]>,
+    /// Hash of `bytes`, computed at build time.
+    pub etag: &'static str,
+}
+impl File {
+    /// Pick the smallest variant the client accepts.
+    ///
+    /// Returns the body and its `Content-Encoding`.
+    pub fn negotiate(&self, accept: &str) -> (&'static [u8], Option<&'static str>) {
+        let mut best: Option<(f32, &'static [u8], &'static str)> = None;
+        for (name, bytes) in [("br", self.br), ("gzip", self.gzip)] {
+            let Some(bytes) = bytes else {
+                continue;
+            };
+            let q = quality(accept, name);
+            if q > 0.0 && best.is_none_or(|x| q > x.0) {
+                best = Some((q, bytes, name));
+            }
+        }
+        match best {
+            Some((_, bytes, name)) => (bytes, Some(name)),
+            None => (self.bytes, None),
+        }
+    }
+
+    /// Respond to `GET` and `HEAD`, honoring `If-None-Match` and single byte `Range`s.
+    pub fn respond(&self, req: &Request<impl Body>, cache: &str) -> Response<Full<Bytes>> {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            return Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(ALLOW, "GET, HEAD")
+                .body(Full::default())
+                .unwrap();
+        }
+        let (bytes, encoding) = self.negotiate(header(req, ACCEPT_ENCODING));
+        // Every variant is a different representation, so it needs its own strong tag.
+        let etag = match encoding {
+            Some(x) => format!("\"{}-{x}\"", self.etag),
+            None => format!("\"{}\"", self.etag),
+        };
+
+        let mut res = Response::builder()
+            .header(CACHE_CONTROL, cache)
+            .header(ETAG, &etag);
+        if self.gzip.is_some() || self.br.is_some() {
+            res = res.header(VARY, "Accept-Encoding");
+        }
+        if req.headers().contains_key(IF_NONE_MATCH)
+            && none_match(header(req, IF_NONE_MATCH), &etag)
+        {
+            return res
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Full::default())
+                .unwrap();
+        }
+
+        res = res
+            .header(CONTENT_TYPE, self.mime)
+            .header(ACCEPT_RANGES, "bytes");
+        if let Some(x) = encoding {
+            res = res.header(CONTENT_ENCODING, x);
+        }
+        // A stale `If-Range` means the client's partial copy is outdated, so it gets everything.
+        let range = req
+            .headers()
+            .contains_key(RANGE)
+            .then(|| header(req, RANGE))
+            .filter(|_| !req.headers().contains_key(IF_RANGE) || header(req, IF_RANGE) == etag)
+            .and_then(|x| range(x, bytes.len()));
+        let body = match range {
+            None => bytes,
+            Some(Ok(x)) => {
+                res = res.status(StatusCode::PARTIAL_CONTENT).header(
+                    CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", x.start, x.end - 1, bytes.len()),
+                );
+                &bytes[x]
+            }
+            Some(Err(())) => {
+                res = res
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(CONTENT_RANGE, format!("bytes */{}", bytes.len()));
+                &[]
+            }
+        };
+        res.body(Full::new(Bytes::from_static(body))).unwrap()
+    }
+}
+
+fn header(req: &Request<impl Body>, name: impl AsHeaderName) -> &str {
+    req.headers()
+        .get(name)
+        .and_then(|x| x.to_str().ok())
+        .unwrap_or_default()
+}
+
+/// Whether `If-None-Match` allows a `304`. Weak comparison, as the RFC requires for it.
+fn none_match(header: &str, etag: &str) -> bool {
+    header.split(',').any(|x| {
+        let x = x.trim();
+        x == "*" || x.strip_prefix("W/").unwrap_or(x) == etag
+    })
+}
+
+/// Parse a `Range` header against a body of `len` bytes.
+///
+/// `None` if it should be ignored (malformed, not in bytes, or several ranges, which aren't
+/// worth supporting), `Err` if it can't be satisfied.
+fn range(header: &str, len: usize) -> Option<Result<Range<usize>, ()>> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.trim().split_once('-')?;
+    let range = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: usize = suffix.parse().ok()?;
+            len.saturating_sub(suffix)..len
+        }
+        (start, "") => start.parse().ok()?..len,
+        (start, end) => {
+            let (start, end): (usize, usize) = (start.parse().ok()?, end.parse().ok()?);
+            if end < start {
+                return None;
+            }
+            start..end.saturating_add(1).min(len)
+        }
+    };
+    Some(match range.start < range.end {
+        true => Ok(range),
+        false => Err(()),
+    })
+}
+
+/// An embedded file served on a fixed path.
+pub struct Route {
+    pub path: &'static str,
+    pub cache: &'static str,
+    pub file: File,
+}
+
+/// Find `path` in `routes`, which are sorted by path.
+pub fn lookup(routes: &'static [Route], path: &str) -> Option<&'static Route> {
+    routes
+        .binary_search_by_key(&path, |x| x.path)
+        .ok()
+        .map(|x| &routes[x])
+}
+
+/// Quality value of `coding` in an `Accept-Encoding` header. `*` matches anything not listed.
+fn quality(accept: &str, coding: &str) -> f32 {
+    let mut wildcard = 0.0;
+    for x in accept.split(',') {
+        let mut params = x.split(';');
+        let name = params.next().unwrap_or_default().trim();
+        let q = params
+            .find_map(|x| x.trim().strip_prefix("q="))
+            .map_or(Some(1.0), |x| x.trim().parse().ok())
+            .unwrap_or(0.0);
+        if name.eq_ignore_ascii_case(coding) {
+            return q;
+        }
+        if name == "*" {
+            wildcard = q;
+        }
+    }
+    wildcard
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+    use http_body_util::BodyExt;
+
+    use super::*;
+
+    const FILE: File = File {
+        bytes: b"0123456789",
+        mime: "text/plain",
+        gzip: Some(b"gzip"),
+        br: Some(b"br"),
+        etag: "tag",
+    };
+
+    fn get(headers: &[(&str, &str)]) -> Response<Full<Bytes>> {
+        let mut req = Request::get("/");
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        FILE.respond(&req.body(Full::<Bytes>::default()).unwrap(), REVALIDATE)
+    }
+
+    fn body(res: Response<Full<Bytes>>) -> Bytes {
+        let body = res.into_body().collect().now_or_never().unwrap().unwrap();
+        body.to_bytes()
+    }
+
+    fn header<'a>(res: &'a Response<Full<Bytes>>, name: &str) -> Option<&'a str> {
+        res.headers().get(name).map(|x| x.to_str().unwrap())
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(range("bytes=2-4", 10), Some(Ok(2..5)));
+        assert_eq!(range(" bytes= 5 - 100 ", 10), Some(Ok(5..10)));
+        assert_eq!(range("bytes=4-", 10), Some(Ok(4..10)));
+        assert_eq!(range("bytes=-3", 10), Some(Ok(7..10)));
+        assert_eq!(range("bytes=-20", 10), Some(Ok(0..10)));
+    }
+
+    #[test]
+    fn unsatisfiable_ranges() {
+        assert_eq!(range("bytes=10-", 10), Some(Err(())));
+        assert_eq!(range("bytes=10-20", 10), Some(Err(())));
+        assert_eq!(range("bytes=-0", 10), Some(Err(())));
+        assert_eq!(range("bytes=0-", 0), Some(Err(())));
+    }
+
+    #[test]
+    fn ignored_ranges() {
+        assert_eq!(range("bytes=0-1,3-4", 10), None);
+        assert_eq!(range("bytes=3-1", 10), None);
+        assert_eq!(range("items=0-1", 10), None);
+        assert_eq!(range("bytes=a-", 10), None);
+        assert_eq!(range("bytes=-", 10), None);
+        assert_eq!(range("bytes=1", 10), None);
+    }
+
+    #[test]
+    fn validators() {
+        assert!(none_match("\"tag\"", "\"tag\""));
+        assert!(none_match("W/\"tag\"", "\"tag\""));
+        assert!(none_match("\"old\", W/\"tag\"", "\"tag\""));
+        assert!(none_match("*", "\"tag\""));
+        assert!(!none_match("\"old\"", "\"tag\""));
+        assert!(!none_match("tag", "\"tag\""));
+    }
+
+    #[test]
+    fn qualities() {
+        assert_eq!(quality("gzip, br", "br"), 1.0);
+        assert_eq!(quality("GZIP;q=0.5", "gzip"), 0.5);
+        assert_eq!(quality("gzip;q=0", "gzip"), 0.0);
+        assert_eq!(quality("gzip", "br"), 0.0);
+        assert_eq!(quality("*", "br"), 1.0);
+        assert_eq!(quality("*;q=0.3, br", "gzip"), 0.3);
+        assert_eq!(quality("gzip;q=0, *", "gzip"), 0.0);
+        assert_eq!(quality("gzip;q=x", "gzip"), 0.0);
+        assert_eq!(quality("", "gzip"), 0.0);
+    }
+
+    #[test]
+    fn encodings() {
+        let res = get(&[("accept-encoding", "gzip, br")]);
+        assert_eq!(header(&res, "content-encoding"), Some("br"));
+        assert_eq!(header(&res, "etag"), Some("\"tag-br\""));
+        assert_eq!(header(&res, "vary"), Some("Accept-Encoding"));
+        assert_eq!(body(res), "br");
+
+        let res = get(&[("accept-encoding", "br;q=0, gzip")]);
+        assert_eq!(header(&res, "content-encoding"), Some("gzip"));
+        assert_eq!(body(res), "gzip");
+
+        let res = get(&[("accept-encoding", "br;q=0.5, gzip;q=0.8")]);
+        assert_eq!(header(&res, "content-encoding"), Some("gzip"));
+
+        let res = get(&[("accept-encoding", "*")]);
+        assert_eq!(header(&res, "content-encoding"), Some("br"));
+
+        let res = get(&[("accept-encoding", "*;q=0")]);
+        assert_eq!(header(&res, "content-encoding"), None);
+        assert_eq!(header(&res, "etag"), Some("\"tag\""));
+        assert_eq!(body(res), "0123456789");
+
+        let res = get(&[]);
+        assert_eq!(header(&res, "content-encoding"), None);
+        assert_eq!(header(&res, "content-type"), Some("text/plain"));
+        assert_eq!(header(&res, "cache-control"), Some(REVALIDATE));
+    }
+
+    #[test]
+    fn not_modified() {
+        let res = get(&[("if-none-match", "W/\"tag\"")]);
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(header(&res, "etag"), Some("\"tag\""));
+        assert_eq!(body(res), "");
+
+        // The tag of another encoding doesn't match.
+        let res = get(&[("if-none-match", "\"tag-br\"")]);
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = get(&[
+            ("accept-encoding", "br"),
+            ("if-none-match", "\"old\", \"tag-br\""),
+        ]);
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[test]
+    fn partial() {
+        let res = get(&[("range", "bytes=-3")]);
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(header(&res, "content-range"), Some("bytes 7-9/10"));
+        assert_eq!(body(res), "789");
+
+        let res = get(&[("range", "bytes=8-")]);
+        assert_eq!(header(&res, "content-range"), Some("bytes 8-9/10"));
+        assert_eq!(body(res), "89");
+
+        // Ranges apply to the encoded body.
+        let res = get(&[("accept-encoding", "gzip"), ("range", "bytes=1-2")]);
+        assert_eq!(header(&res, "content-range"), Some("bytes 1-2/4"));
+        assert_eq!(body(res), "zi");
+    }
+
+    #[test]
+    fn unsatisfiable() {
+        let res = get(&[("range", "bytes=10-")]);
+        assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(header(&res, "content-range"), Some("bytes */10"));
+        assert_eq!(body(res), "");
+    }
+
+    #[test]
+    fn full_instead_of_range() {
+        for headers in [
+            &[("range", "bytes=0-1,4-5")][..],
+            &[("range", "bytes=5-1")],
+            &[("range", "bytes=0-1"), ("if-range", "\"old\"")],
+        ] {
+            let res = get(headers);
+            assert_eq!(res.status(), StatusCode::OK, "{headers:?}");
+            assert_eq!(header(&res, "content-range"), None);
+            assert_eq!(body(res), "0123456789");
+        }
+
+        let res = get(&[("range", "bytes=0-1"), ("if-range", "\"tag\"")]);
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    }
+
+    #[test]
+    fn methods() {
+        let req = Request::post("/").body(Full::<Bytes>::default()).unwrap();
+        let res = FILE.respond(&req, REVALIDATE);
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(header(&res, "allow"), Some("GET, HEAD"));
+
+        let req = Request::head("/").body(Full::<Bytes>::default()).unwrap();
+        assert_eq!(FILE.respond(&req, REVALIDATE).status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,136 @@
+//! Security headers, see [`Headers`](crate::config::Headers).
+//!
+//! The defaults make pages cross-origin isolated, so the client could use `SharedArrayBuffer`.
+
+use std::{collections::BTreeMap, error::Error};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hyper::{
+    header::{HeaderName, HeaderValue},
+    HeaderMap, Response,
+};
+use ring::digest::{digest, SHA256};
+
+use crate::{config, pages};
+
+/// Kinds of responses with their own headers.
+#[derive(Clone, Copy)]
+pub enum Group {
+    Pages,
+    Client,
+    Assets,
+    Other,
+}
+impl Group {
+    /// Group of a response to `path` that didn't set one in its extensions.
+    pub fn of(path: &str) -> Self {
+        if path == "/play" || path.starts_with("/client/") {
+            Self::Client
+        } else if path.starts_with("/assets/") {
+            Self::Assets
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/// CSP source allowing an inline script or style.
+fn sha256(source: &str) -> String {
+    let hash = digest(&SHA256, source.as_bytes());
+    format!("'sha256-{}'", STANDARD.encode(hash))
+}
+
+/// Contents of the inline `<script>`s in `html`.
+fn inline_scripts(html: &str) -> Vec<&str> {
+    let mut scripts = vec![];
+    let mut rest = html;
+    while let Some(start) = rest.find("<script") {
+        rest = &rest[start..];
+        let (Some(open), Some(close)) = (rest.find('>'), rest.find("</script")) else {
+            break;
+        };
+        if close > open && !rest[..open].contains("src=") {
+            scripts.push(&rest[open + 1..close]);
+        }
+        rest = &rest[close.max(open)..];
+    }
+    scripts
+}
+
+fn defaults(group: Group, client: &str) -> Vec<(&'static str, String)> {
+    let mut headers = vec![
+        ("x-content-type-options", "nosniff".to_owned()),
+        ("referrer-policy", "no-referrer".to_owned()),
+        ("cross-origin-opener-policy", "same-origin".to_owned()),
+        ("cross-origin-embedder-policy", "require-corp".to_owned()),
+        ("cross-origin-resource-policy", "same-origin".to_owned()),
+    ];
+    let csp = match group {
+        Group::Pages => format!(
+            "default-src 'none'; style-src {}; base-uri 'none'; form-action 'none'; \
+             frame-ancestors 'none'",
+            sha256(pages::STYLE)
+        ),
+        Group::Client => {
+            let scripts: Vec<_> = inline_scripts(client).into_iter().map(sha256).collect();
+            format!(
+                "default-src 'self'; script-src 'self' 'wasm-unsafe-eval' {}; object-src 'none'; \
+                 base-uri 'none'; form-action 'none'; frame-ancestors 'none'",
+                scripts.join(" ")
+            )
+        }
+        Group::Assets => "default-src 'none'; sandbox".to_owned(),
+        Group::Other => return headers,
+    };
+    headers.push(("content-security-policy", csp));
+    headers
+}
+
+/// Headers of every [`Group`].
+pub struct Headers(Vec<HeaderMap>);
+impl Headers {
+    /// Defaults overridden by `config`. The client CSP allows the inline scripts of `client`, the
+    /// web client's `index.html`.
+    pub fn new(
+        config: &config::Headers,
+        client: &str,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let none = BTreeMap::new();
+        let groups = [
+            (Group::Pages, &config.pages),
+            (Group::Client, &config.client),
+            (Group::Assets, &config.assets),
+            (Group::Other, &none),
+        ];
+        let mut maps = vec![];
+        for (group, overrides) in groups {
+            let mut map = HeaderMap::new();
+            let overrides = config.all.iter().chain(overrides);
+            let all = defaults(group, client)
+                .into_iter()
+                .chain(overrides.map(|(k, v)| (k.as_str(), v.clone())));
+            for (name, value) in all {
+                let name = HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| format!("invalid header name '{name}'"))?;
+                if value.is_empty() {
+                    map.remove(&name);
+                    continue;
+                }
+                let value = HeaderValue::from_str(&value)
+                    .map_err(|_| format!("invalid value for header '{name}'"))?;
+                map.insert(name, value);
+            }
+            maps.push(map);
+        }
+        Ok(Self(maps))
+    }
+
+    /// Add the headers of `group` that `res` doesn't set itself.
+    pub fn apply<B>(&self, group: Group, res: &mut Response<B>) {
+        for (name, value) in &self.0[group as usize] {
+            res.headers_mut()
+                .entry(name)
+                .or_insert_with(|| value.clone());
+        }
+    }
+}
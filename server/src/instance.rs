@@ -0,0 +1,246 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use app::sim::{World, TICK_RATE};
+use protocol::{Entity, ErrorCode, Input, RoomInfo, ServerMessage, Snapshot};
+use tokio::{
+    spawn,
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
+    time::{interval, MissedTickBehavior},
+};
+
+/// Longest chat message relayed to other players, in characters.
+const MAX_CHAT: usize = 256;
+
+pub enum Command {
+    Join {
+        id: u32,
+        tx: UnboundedSender<ServerMessage>,
+        room: RoomInfo,
+        code: Option<String>,
+    },
+    Leave(u32),
+    Input(u32, Input),
+    Chat(u32, String),
+    /// Player `by` asks to remove player `id`. Only done if `by` is the host.
+    Kick {
+        by: u32,
+        id: u32,
+    },
+    /// Stop the instance, replying once every player has been sent the final state of the game.
+    Shutdown(oneshot::Sender<()>),
+}
+
+/// Handle to a game instance running on its own task.
+#[derive(Clone)]
+pub struct Instance(UnboundedSender<Command>);
+impl Instance {
+    /// Run the game of room `room`.
+    pub fn spawn(room: u32) -> Self {
+        let (tx, rx) = unbounded_channel();
+        spawn(run(room, rx));
+        Self(tx)
+    }
+
+    pub fn send(&self, command: Command) {
+        let _ = self.0.send(command);
+    }
+}
+
+async fn run(room: u32, mut commands: UnboundedReceiver<Command>) {
+    let mut world = World::default();
+    let mut clients: BTreeMap<u32, UnboundedSender<ServerMessage>> = BTreeMap::new();
+    // Entities clients received last tick, deltas are relative to them.
+    let mut sent: Vec<Entity> = vec![];
+    // Players in order of joining. The first one is the host.
+    let mut order: Vec<u32> = vec![];
+
+    let mut ticker = interval(Duration::from_secs(1) / TICK_RATE);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            command = commands.recv() => match command {
+                Some(Command::Join { id, tx, room, code }) => {
+                    world.join(id);
+                    order.push(id);
+                    let _ = tx.send(ServerMessage::Joined {
+                        room,
+                        code,
+                        host: order[0],
+                        tick: world.tick,
+                    });
+                    let _ = tx.send(ServerMessage::Snapshot(Snapshot {
+                        tick: world.tick,
+                        ack: 0,
+                        entities: sent.clone(),
+                    }));
+                    clients.insert(id, tx);
+                }
+                Some(Command::Leave(id)) => {
+                    world.leave(id);
+                    clients.remove(&id);
+                    let was_host = order.first() == Some(&id);
+                    order.retain(|x| *x != id);
+                    if let Some(host) = order.first().filter(|_| was_host) {
+                        for x in clients.values() {
+                            let _ = x.send(ServerMessage::Host(*host));
+                        }
+                    }
+                }
+                Some(Command::Kick { by, id }) => {
+                    if order.first() != Some(&by) {
+                        if let Some(x) = clients.get(&by) {
+                            let _ = x.send(ServerMessage::Error {
+                                code: ErrorCode::NotHost,
+                                message: "only the host can kick players".into(),
+                            });
+                        }
+                    } else if id != by {
+                        if let Some(x) = clients.remove(&id) {
+                            // Its session frees the slot once it gets this.
+                            let _ = x.send(ServerMessage::Kicked(room));
+                            world.leave(id);
+                            order.retain(|x| *x != id);
+                        }
+                    }
+                }
+                Some(Command::Input(id, input)) => world.input(id, input),
+                Some(Command::Chat(from, text)) => {
+                    let text: String = text.chars().take(MAX_CHAT).collect();
+                    for x in clients.values() {
+                        let _ = x.send(ServerMessage::Chat { from, text: text.clone() });
+                    }
+                }
+                Some(Command::Shutdown(x)) => {
+                    for (id, x) in &clients {
+                        let _ = x.send(ServerMessage::Snapshot(world.snapshot(*id)));
+                    }
+                    let _ = x.send(());
+                    break;
+                }
+                None => break,
+            },
+            _ = ticker.tick() => {
+                let base = world.tick;
+                world.step();
+                // Regular snapshots let clients that missed a delta catch up.
+                let full = world.tick % TICK_RATE == 0;
+                clients.retain(|id, x| {
+                    let message = match full {
+                        true => ServerMessage::Snapshot(world.snapshot(*id)),
+                        false => ServerMessage::Delta(world.delta(base, &sent, *id)),
+                    };
+                    x.send(message).is_ok()
+                });
+                sent = world.entities();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::timeout;
+
+    use super::*;
+
+    /// Next message other than the per tick snapshots and deltas.
+    async fn next(rx: &mut UnboundedReceiver<ServerMessage>) -> Option<ServerMessage> {
+        let wait = async {
+            loop {
+                match rx.recv().await {
+                    Some(ServerMessage::Snapshot(_) | ServerMessage::Delta(_)) => {}
+                    x => return x,
+                }
+            }
+        };
+        timeout(Duration::from_secs(5), wait)
+            .await
+            .expect("no message")
+    }
+
+    async fn join(instance: &Instance, id: u32) -> UnboundedReceiver<ServerMessage> {
+        let (tx, mut rx) = unbounded_channel();
+        instance.send(Command::Join {
+            id,
+            tx,
+            room: RoomInfo::default(),
+            code: None,
+        });
+        assert!(matches!(
+            next(&mut rx).await,
+            Some(ServerMessage::Joined { .. })
+        ));
+        rx
+    }
+
+    /// Chat from `from`, seen by every receiver in `rxs`.
+    async fn chat(
+        instance: &Instance,
+        from: u32,
+        rxs: &mut [&mut UnboundedReceiver<ServerMessage>],
+    ) {
+        instance.send(Command::Chat(from, "hi".into()));
+        for rx in rxs {
+            assert_eq!(
+                next(rx).await,
+                Some(ServerMessage::Chat {
+                    from,
+                    text: "hi".into()
+                })
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn kick() {
+        let instance = Instance::spawn(7);
+        let mut host = join(&instance, 1).await;
+        let mut other = join(&instance, 2).await;
+        let mut third = join(&instance, 3).await;
+
+        // Only the host can kick.
+        instance.send(Command::Kick { by: 2, id: 3 });
+        assert!(matches!(
+            next(&mut other).await,
+            Some(ServerMessage::Error {
+                code: ErrorCode::NotHost,
+                ..
+            })
+        ));
+        chat(&instance, 1, &mut [&mut host, &mut other, &mut third]).await;
+
+        // The host can't kick themselves, unknown players are ignored.
+        instance.send(Command::Kick { by: 1, id: 1 });
+        instance.send(Command::Kick { by: 1, id: 9 });
+        chat(&instance, 2, &mut [&mut host, &mut other, &mut third]).await;
+
+        instance.send(Command::Kick { by: 1, id: 3 });
+        assert_eq!(next(&mut third).await, Some(ServerMessage::Kicked(7)));
+        // Dropped by the instance, so nothing follows.
+        assert_eq!(next(&mut third).await, None);
+        chat(&instance, 2, &mut [&mut host, &mut other]).await;
+
+        // The host is still the host.
+        instance.send(Command::Leave(1));
+        assert_eq!(next(&mut other).await, Some(ServerMessage::Host(2)));
+    }
+
+    #[tokio::test]
+    async fn shutdown() {
+        let instance = Instance::spawn(7);
+        let mut rx = join(&instance, 1).await;
+        let (tx, stopped) = oneshot::channel();
+        instance.send(Command::Shutdown(tx));
+        stopped.await.unwrap();
+        // The final state arrives before the instance lets go of the player.
+        let mut last = None;
+        while let Some(x) = rx.recv().await {
+            last = Some(x);
+        }
+        assert!(matches!(last, Some(ServerMessage::Snapshot(x)) if !x.entities.is_empty()));
+    }
+}
@@ -0,0 +1,239 @@
+//! Rate limits and connection caps per client, see [`Limits`].
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv6Addr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::config::{Limits, Rate};
+
+/// Clients sharing a limit: IPv6 addresses by /64, since that's what one usually gets.
+fn key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => ip,
+        IpAddr::V6(x) => match x.to_ipv4_mapped() {
+            Some(x) => IpAddr::V4(x),
+            None => IpAddr::V6(Ipv6Addr::from(x.to_bits() & !0 << 64)),
+        },
+    }
+}
+
+pub struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+impl Bucket {
+    pub fn new(rate: Rate) -> Self {
+        Self {
+            tokens: rate.burst.into(),
+            updated: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, rate: Rate) {
+        let now = Instant::now();
+        let elapsed = (now - self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate.per_second).min(rate.burst.into());
+        self.updated = now;
+    }
+
+    /// Take a token, or tell how long until there is one.
+    pub fn take(&mut self, rate: Rate) -> Result<(), Duration> {
+        self.refill(rate);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        // Tiny rates can take longer than a `Duration` holds.
+        let wait = (1.0 - self.tokens) / rate.per_second;
+        Err(Duration::try_from_secs_f64(wait).unwrap_or(Duration::MAX))
+    }
+}
+
+struct Client {
+    requests: Bucket,
+    connections: u32,
+}
+
+pub struct Limiter {
+    config: Limits,
+    clients: Mutex<HashMap<IpAddr, Client>>,
+}
+impl Limiter {
+    /// How often [`Limiter::cleanup`] should be called.
+    pub const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+    pub fn new(config: Limits) -> Self {
+        Self {
+            config,
+            clients: Default::default(),
+        }
+    }
+
+    fn client<'a>(&self, clients: &'a mut HashMap<IpAddr, Client>, ip: IpAddr) -> &'a mut Client {
+        clients.entry(key(ip)).or_insert_with(|| Client {
+            requests: Bucket::new(self.config.requests),
+            connections: 0,
+        })
+    }
+
+    /// Count a connection from `client` until the permit is dropped. Fails if it has too many
+    /// already.
+    pub fn connect(self: &Arc<Self>, client: Option<IpAddr>) -> Option<Permit> {
+        let Some(ip) = client else {
+            return Some(Permit(None));
+        };
+        let mut clients = self.clients.lock().unwrap();
+        let client = self.client(&mut clients, ip);
+        if client.connections >= self.config.max_connections {
+            return None;
+        }
+        client.connections += 1;
+        Some(Permit(Some((self.clone(), ip))))
+    }
+
+    /// Take a request from `client`'s budget, or tell how long until it can make another.
+    pub fn request(&self, client: Option<IpAddr>) -> Result<(), Duration> {
+        let Some(ip) = client else {
+            return Ok(());
+        };
+        let mut clients = self.clients.lock().unwrap();
+        self.client(&mut clients, ip)
+            .requests
+            .take(self.config.requests)
+    }
+
+    /// Forget clients without connections whose budget is full again.
+    pub fn cleanup(&self) {
+        let rate = self.config.requests;
+        self.clients.lock().unwrap().retain(|_, x| {
+            x.requests.refill(rate);
+            x.connections > 0 || x.requests.tokens < rate.burst.into()
+        });
+    }
+}
+
+/// An open connection, see [`Limiter::connect`].
+pub struct Permit(Option<(Arc<Limiter>, IpAddr)>);
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some((limiter, ip)) = &self.0 {
+            let mut clients = limiter.clients.lock().unwrap();
+            if let Some(x) = clients.get_mut(&key(*ip)) {
+                x.connections -= 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: Rate = Rate {
+        per_second: 2.0,
+        burst: 3,
+    };
+
+    fn limiter(max_connections: u32) -> Arc<Limiter> {
+        Arc::new(Limiter::new(Limits {
+            requests: RATE,
+            messages: RATE,
+            max_connections,
+        }))
+    }
+
+    fn ip(x: &str) -> Option<IpAddr> {
+        Some(x.parse().unwrap())
+    }
+
+    #[test]
+    fn bucket() {
+        let mut bucket = Bucket::new(RATE);
+        for _ in 0..3 {
+            assert_eq!(bucket.take(RATE), Ok(()));
+        }
+        let wait = bucket.take(RATE).unwrap_err();
+        assert!(wait > Duration::from_millis(490) && wait <= Duration::from_millis(500));
+
+        bucket.updated -= Duration::from_millis(500);
+        assert_eq!(bucket.take(RATE), Ok(()));
+        assert!(bucket.take(RATE).is_err());
+
+        // Refilling stops at `burst`.
+        bucket.updated -= Duration::from_secs(60);
+        for _ in 0..3 {
+            assert_eq!(bucket.take(RATE), Ok(()));
+        }
+        assert!(bucket.take(RATE).is_err());
+    }
+
+    #[test]
+    fn slow_bucket() {
+        let rate = Rate {
+            per_second: 1e-300,
+            burst: 0,
+        };
+        assert_eq!(Bucket::new(rate).take(rate), Err(Duration::MAX));
+    }
+
+    #[test]
+    fn requests() {
+        let limiter = limiter(1);
+        for _ in 0..3 {
+            assert_eq!(limiter.request(ip("10.0.0.1")), Ok(()));
+        }
+        assert!(limiter.request(ip("10.0.0.1")).is_err());
+        assert!(limiter.request(ip("::ffff:10.0.0.1")).is_err());
+        assert_eq!(limiter.request(ip("10.0.0.2")), Ok(()));
+        // Unix socket clients aren't limited.
+        for _ in 0..10 {
+            assert_eq!(limiter.request(None), Ok(()));
+        }
+    }
+
+    #[test]
+    fn connections() {
+        let limiter = limiter(2);
+        let a = limiter.connect(ip("2001:db8::1")).unwrap();
+        let _b = limiter.connect(ip("2001:db8::2")).unwrap();
+        assert!(limiter.connect(ip("2001:db8::3")).is_none());
+        assert!(limiter.connect(ip("2001:db8:0:1::1")).is_some());
+        drop(a);
+        assert!(limiter.connect(ip("2001:db8::3")).is_some());
+
+        let permits: Vec<_> = (0..10).map(|_| limiter.connect(None)).collect();
+        assert!(permits.iter().all(Option::is_some));
+    }
+
+    #[test]
+    fn cleanup() {
+        let limiter = limiter(2);
+        let permit = limiter.connect(ip("10.0.0.1"));
+        limiter.request(ip("10.0.0.2")).unwrap();
+        let _ = limiter.connect(ip("10.0.0.3"));
+        limiter.cleanup();
+        let mut ips: Vec<_> = limiter.clients.lock().unwrap().keys().copied().collect();
+        ips.sort();
+        assert_eq!(ips, [ip("10.0.0.1").unwrap(), ip("10.0.0.2").unwrap()]);
+
+        drop(permit);
+        limiter.clients.lock().unwrap().values_mut().for_each(|x| {
+            x.requests.updated -= Duration::from_secs(60);
+        });
+        limiter.cleanup();
+        assert!(limiter.clients.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn keys() {
+        let key = |x: &str| key(x.parse().unwrap()).to_string();
+        assert_eq!(key("192.0.2.1"), "192.0.2.1");
+        assert_eq!(key("::ffff:192.0.2.1"), "192.0.2.1");
+        assert_eq!(key("2001:db8:1:2:3:4:5:6"), "2001:db8:1:2::");
+        assert_eq!(key("2001:db8:1:2::"), "2001:db8:1:2::");
+        assert_eq!(key("::1"), "::");
+    }
+}
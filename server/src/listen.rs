@@ -0,0 +1,238 @@
+//! Sockets connections are accepted on: TCP, unix domain sockets, or either passed by systemd.
+
+use std::{
+    error::Error,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+};
+#[cfg(unix)]
+use std::{
+    fs,
+    os::{
+        fd::{FromRawFd, OwnedFd},
+        unix::fs::{FileTypeExt, PermissionsExt},
+    },
+    path::PathBuf,
+};
+
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::config::{Bind, Listen};
+
+/// Sockets passed by systemd socket activation, see `sd_listen_fds(3)`.
+#[derive(Default)]
+pub struct Inherited {
+    #[cfg(unix)]
+    fds: Vec<(String, Option<OwnedFd>)>,
+}
+impl Inherited {
+    /// Take the sockets from the environment, if it's meant for this process.
+    ///
+    /// The variables are removed like `sd_listen_fds(3)` can do, so child processes don't think
+    /// the sockets are theirs. Changing the environment is only sound while no other thread runs,
+    /// so call this before starting the runtime.
+    #[cfg(unix)]
+    pub fn from_env() -> Self {
+        let var = |x| std::env::var(x).ok();
+        let (pid, count, names) = (var("LISTEN_PID"), var("LISTEN_FDS"), var("LISTEN_FDNAMES"));
+        for x in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+            std::env::remove_var(x);
+        }
+        if pid.and_then(|x| x.parse().ok()) != Some(std::process::id()) {
+            return Self::default();
+        }
+        let count: i32 = count.and_then(|x| x.parse().ok()).unwrap_or(0);
+        let names = names.unwrap_or_default();
+        let mut names = names.split(':');
+        let fds = (0..count)
+            .map(|i| {
+                let name = names.next().unwrap_or("unknown").to_owned();
+                // SAFETY: passed sockets start at 3 and belong to this process alone.
+                let fd = unsafe { OwnedFd::from_raw_fd(3 + i) };
+                (name, Some(fd))
+            })
+            .collect();
+        Self { fds }
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_env() -> Self {
+        Self::default()
+    }
+
+    /// Socket names nobody took.
+    pub fn unused(&self) -> Vec<&str> {
+        #[cfg(unix)]
+        return self
+            .fds
+            .iter()
+            .filter(|x| x.1.is_some())
+            .map(|x| x.0.as_str())
+            .collect();
+        #[cfg(not(unix))]
+        vec![]
+    }
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    /// Socket file to remove when done, unless inherited.
+    #[cfg(unix)]
+    Unix(UnixListener, Option<PathBuf>),
+}
+impl Listener {
+    pub async fn bind(
+        config: &Listen,
+        inherited: &mut Inherited,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Self::open(config, inherited)
+            .await
+            .map_err(|why| format!("{}: {why}", config.bind).into())
+    }
+
+    async fn open(config: &Listen, inherited: &mut Inherited) -> io::Result<Self> {
+        match &config.bind {
+            Bind::Tcp(x) => Ok(Self::Tcp(TcpListener::bind(x).await?)),
+            #[cfg(unix)]
+            Bind::Unix(path) => {
+                if fs::symlink_metadata(path).is_ok_and(|x| x.file_type().is_socket()) {
+                    // A socket nobody listens on is left over from a crash, a live one isn't.
+                    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AddrInUse,
+                            "another process is listening on it",
+                        ));
+                    }
+                    fs::remove_file(path)?;
+                }
+                let Some(mode) = config.mode else {
+                    return Ok(Self::Unix(UnixListener::bind(path)?, Some(path.clone())));
+                };
+                // The socket only appears under its name once it has the right mode.
+                let mut name = path.file_name().unwrap_or_default().to_owned();
+                name.push(format!(".{}.tmp", std::process::id()));
+                let temp = path.with_file_name(name);
+                let _ = fs::remove_file(&temp);
+                let listener = UnixListener::bind(&temp)?;
+                let placed = fs::set_permissions(&temp, fs::Permissions::from_mode(mode))
+                    .and_then(|()| fs::rename(&temp, path));
+                if let Err(why) = placed {
+                    let _ = fs::remove_file(&temp);
+                    return Err(why);
+                }
+                Ok(Self::Unix(listener, Some(path.clone())))
+            }
+            #[cfg(unix)]
+            Bind::Systemd(name) => {
+                let fd = inherited
+                    .fds
+                    .iter_mut()
+                    .find(|x| x.0 == *name)
+                    .and_then(|x| x.1.take())
+                    .ok_or_else(|| {
+                        io::Error::other("not passed by systemd (see LISTEN_FDNAMES)")
+                    })?;
+                let unix = std::os::unix::net::UnixListener::from(fd);
+                // Only unix sockets have an address of the unix family.
+                if unix.local_addr().is_ok() {
+                    unix.set_nonblocking(true)?;
+                    return Ok(Self::Unix(UnixListener::from_std(unix)?, None));
+                }
+                let tcp = std::net::TcpListener::from(OwnedFd::from(unix));
+                tcp.set_nonblocking(true)?;
+                Ok(Self::Tcp(TcpListener::from_std(tcp)?))
+            }
+            #[cfg(not(unix))]
+            _ => {
+                let _ = inherited;
+                Err(io::Error::other("only supported on unix"))
+            }
+        }
+    }
+
+    /// Wait for a connection. Only TCP peers have an address.
+    pub async fn accept(&self) -> io::Result<(Stream, Option<SocketAddr>)> {
+        match self {
+            Self::Tcp(x) => {
+                let (stream, addr) = x.accept().await?;
+                Ok((Stream::Tcp(stream), Some(addr)))
+            }
+            #[cfg(unix)]
+            Self::Unix(x, _) => Ok((Stream::Unix(x.accept().await?.0), None)),
+        }
+    }
+}
+#[cfg(unix)]
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Self::Unix(_, Some(path)) = self {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+pub enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+macro_rules! delegate {
+    ($self:ident, $x:ident => $e:expr) => {
+        match $self.get_mut() {
+            Stream::Tcp($x) => $e,
+            #[cfg(unix)]
+            Stream::Unix($x) => $e,
+        }
+    };
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        delegate!(self, x => Pin::new(x).poll_read(cx, buf))
+    }
+}
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        delegate!(self, x => Pin::new(x).poll_write(cx, buf))
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        delegate!(self, x => Pin::new(x).poll_write_vectored(cx, bufs))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Stream::Tcp(x) => x.is_write_vectored(),
+            #[cfg(unix)]
+            Stream::Unix(x) => x.is_write_vectored(),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        delegate!(self, x => Pin::new(x).poll_flush(cx))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        delegate!(self, x => Pin::new(x).poll_shutdown(cx))
+    }
+}
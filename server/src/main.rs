@@ -1,107 +1,666 @@
 #[macro_use]
 extern crate log;
 
-use std::{convert::Infallible, fs, process::exit};
+use std::{
+    collections::hash_map::RandomState,
+    convert::Infallible,
+    fs,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    net::IpAddr,
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    pin::{pin, Pin},
+    process::exit,
+    sync::{atomic::Ordering::Relaxed, Arc},
+    time::{Duration, Instant},
+};
 
-use assets::include_resources;
-use config::Config;
+use assets::{MANIFEST, SOURCE_DIR};
+use config::{AccessLog, Config, Limits, Netsim};
+use files::{File, Route};
+use headers::{Group, Headers};
 use http_body_util::Full;
 use hyper::{
     body::{Body, Bytes},
+    header::{CONTENT_LENGTH, LOCATION},
+    rt::{Read, Write},
     server::conn::http1,
     service::service_fn,
-    Request, Response,
+    Method, Request, Response, StatusCode,
+};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo, TokioTimer},
+    server::conn::auto,
 };
-use hyper_util::rt::{TokioIo, TokioTimer};
-use maud::DOCTYPE;
-use tokio::{net::TcpListener, spawn};
+use limits::{Limiter, Permit};
+use listen::{Inherited, Listener, Stream};
+use metrics::Metrics;
+use proxy::Proxies;
+use rooms::Rooms;
+use tokio::{
+    select, signal, spawn,
+    sync::watch,
+    task::JoinSet,
+    time::{interval, timeout_at},
+};
+use tokio_rustls::TlsAcceptor;
 
+mod access;
 mod config;
+#[cfg(feature = "dev")]
+mod dev;
+mod files;
+mod headers;
+mod instance;
+mod limits;
+mod listen;
+mod metrics;
+mod netsim;
+mod pages;
+mod proxy;
+mod rooms;
+mod tls;
+mod ws;
+
+/// Random number, not suitable for anything secret.
+fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// How long connections and rooms get to finish after a shutdown starts.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Embedded web client, see `build.rs`.
+static CLIENT: &[Route] = include!(concat!(env!("OUT_DIR"), "/client_files.rs"));
+
+/// Shared by all connections.
+struct State {
+    rooms: Arc<Rooms>,
+    netsim: Netsim,
+    /// Web client directory when serving files from disk.
+    #[cfg(feature = "dev")]
+    dev: Option<PathBuf>,
+    /// Becomes `true` once the server is shutting down.
+    shutdown: watch::Receiver<bool>,
+    limits: Limits,
+    limiter: Arc<Limiter>,
+    headers: Headers,
+    access_log: AccessLog,
+    metrics: Metrics,
+    /// Where metrics are served, if at all.
+    metrics_path: Option<String>,
+}
+
+/// Web client file at `path` under `/client`.
+fn client(req: &Request<impl Body>, path: &str) -> Option<Response<Full<Bytes>>> {
+    let x = files::lookup(CLIENT, path)?;
+    Some(x.file.respond(req, x.cache))
+}
+
+/// Asset at `path` under `/assets`.
+///
+/// Assets are available both under `/assets/<path>` and `/assets/<hash>/<path>`. The latter
+/// never changes, so it can be cached forever.
+fn asset(req: &Request<impl Body>, path: &str) -> Option<Response<Full<Bytes>>> {
+    let find = |path: &str| {
+        MANIFEST
+            .binary_search_by_key(&path, |x| x.path)
+            .ok()
+            .map(|x| &MANIFEST[x])
+    };
+    let (x, cache) = match find(path) {
+        Some(x) => (x, files::REVALIDATE),
+        None => {
+            let rest = path.strip_prefix('/')?;
+            let (hash, path) = rest.split_at(rest.find('/')?);
+            let x = find(path).filter(|x| x.hash == hash)?;
+            (x, files::IMMUTABLE)
+        }
+    };
+    let file = File {
+        bytes: x.bytes,
+        mime: x.mime,
+        gzip: x.gzip,
+        br: x.br,
+        etag: x.hash,
+    };
+    Some(file.respond(req, cache))
+}
+
+fn route(
+    req: &mut Request<impl Body>,
+    state: &State,
+    addr: Option<IpAddr>,
+) -> Response<Full<Bytes>> {
+    let path = req.uri().path().to_owned();
+    if path.len() > 1 && path.ends_with('/') {
+        let mut location = match path.trim_end_matches('/') {
+            "" => "/".to_owned(),
+            x => x.to_owned(),
+        };
+        if let Some(x) = req.uri().query() {
+            location = format!("{location}?{x}");
+        }
+        return moved(&location);
+    }
 
-async fn service(req: Request<impl Body>) -> Result<Response<Full<Bytes>>, Infallible> {
-    include!(concat!(env!("OUT_DIR"), "/client_files.rs"));
+    if state.metrics_path.as_ref() == Some(&path) {
+        return state.metrics.respond(&state.rooms);
+    }
+
+    let res = match path.as_str() {
+        "/" => Some(pages::landing(&state.rooms.list())),
+        "/play" => client(req, "/index.html"),
+        protocol::PATH => Some(ws::upgrade(
+            req,
+            state.rooms.clone(),
+            state.netsim,
+            state.shutdown.clone(),
+            state.limits.messages,
+            addr,
+        )),
+        x => {
+            if let Some(x) = x.strip_prefix("/client") {
+                client(req, x)
+            } else if let Some(x) = x.strip_prefix("/assets") {
+                asset(req, x)
+            } else {
+                None
+            }
+        }
+    };
+    res.unwrap_or_else(pages::not_found)
+}
 
-    include_resources!(
-        x.png => if req.uri().path() == format!("/assets{}", x.path).as_str() {
-            return Ok(Response::builder()
-                .header("Content-Type", x.mime)
-                .body(Full::new(Bytes::from_static(x.bytes))).unwrap());
+/// Client or asset at the request's path, read from disk in dev mode.
+#[cfg(feature = "dev")]
+async fn dev(req: &Request<impl Body>, state: &State) -> Option<Response<Full<Bytes>>> {
+    let dir = state.dev.as_ref()?;
+    match req.uri().path() {
+        "/play" => dev::client(dir, "/index.html").await,
+        x => match (x.strip_prefix("/client"), x.strip_prefix("/assets")) {
+            (Some(x), _) => dev::client(dir, x).await,
+            (_, Some(x)) => dev::asset(x).await,
+            _ => None,
         },
-        x.ttf => if req.uri().path() == format!("/assets{}", x.path).as_str() {
-            return Ok(Response::builder()
-                .header("Content-Type", x.mime)
-                .body(Full::new(Bytes::from_static(x.bytes))).unwrap());
+    }
+}
+
+#[cfg(not(feature = "dev"))]
+async fn dev(_: &Request<impl Body>, _: &State) -> Option<Response<Full<Bytes>>> {
+    None
+}
+
+/// Serve a request that came through a connection from `peer`.
+async fn service(
+    mut req: Request<impl Body>,
+    state: Arc<State>,
+    endpoint: Arc<Endpoint>,
+    peer: Option<IpAddr>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let start = Instant::now();
+    let head = req.method() == Method::HEAD;
+    let client = endpoint.proxies.client(&req, peer);
+    let mut res = match (state.limiter.request(client), &endpoint.redirect) {
+        (Err(wait), _) => pages::too_many_requests(wait),
+        (Ok(()), Some(x)) => redirect(&req, x),
+        (Ok(()), None) => match dev(&req, &state).await {
+            Some(x) => x,
+            // The panic itself is already logged by the default hook.
+            None => catch_unwind(AssertUnwindSafe(|| route(&mut req, &state, client)))
+                .unwrap_or_else(|_| pages::error()),
         },
-    );
+    };
+    // HTTP/2 connections don't drop the body of `HEAD` responses on their own.
+    if head {
+        let len = res.body().size_hint().exact().unwrap_or_default();
+        res.headers_mut()
+            .entry(CONTENT_LENGTH)
+            .or_insert(len.into());
+        *res.body_mut() = Full::default();
+    }
+    let group = res.extensions().get::<Group>().copied();
+    let group = group.unwrap_or_else(|| Group::of(req.uri().path()));
+    state.headers.apply(group, &mut res);
+    let bytes = res.body().size_hint().exact().unwrap_or_default();
+    let elapsed = start.elapsed();
+    state
+        .metrics
+        .request(req.method(), res.status(), bytes, elapsed);
+    access::log(state.access_log, client, &req, &res, bytes, elapsed);
+    Ok(res)
+}
 
-    Ok(Response::builder()
-        .status(404)
-        .header("Content-Type", "text/html")
-        .body(Full::new(Bytes::from(
-            maud::html! {
-                (DOCTYPE)
-                html {
-                    head {
-                        meta charset="UTF-8";
-                        title { "404 | Not Found" }
-                    }
-                    body {
-                        h1 { "Not Found" }
-                        p { "This url could not be found on this server" }
-                    }
-                }
+fn moved(location: &str) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(StatusCode::PERMANENT_REDIRECT)
+        .header(LOCATION, location)
+        .body(Full::default())
+        .unwrap()
+}
+
+/// Send the client to the same path on another origin.
+fn redirect(req: &Request<impl Body>, origin: &str) -> Response<Full<Bytes>> {
+    let path = req.uri().path_and_query().map_or("/", |x| x.as_str());
+    moved(&format!("{}{path}", origin.trim_end_matches('/')))
+}
+
+/// Drive `conn` to completion, letting it wind down gracefully once a shutdown starts.
+async fn until_shutdown<C: Future>(
+    conn: C,
+    mut shutdown: watch::Receiver<bool>,
+    graceful: impl FnOnce(Pin<&mut C>),
+) -> C::Output {
+    let mut conn = pin!(conn);
+    select! {
+        x = conn.as_mut() => return x,
+        _ = shutdown.wait_for(|x| *x) => graceful(conn.as_mut()),
+    }
+    conn.await
+}
+
+/// Count a new connection against its client, `None` if it has too many already.
+fn admit(state: &State, client: Option<IpAddr>) -> Option<Arc<Permit>> {
+    let permit = state.limiter.connect(client).map(Arc::new);
+    if let (None, Some(x)) = (&permit, client) {
+        debug!("Too many connections from {x}");
+    }
+    permit
+}
+
+/// Settings of one listener.
+struct Endpoint {
+    http2: bool,
+    /// Origin everything is redirected to instead of being served.
+    redirect: Option<String>,
+    tls: Option<TlsAcceptor>,
+    proxies: Proxies,
+}
+
+/// Accept connections until shutdown. Returns whether it stopped because of the shutdown.
+async fn listen(listener: Listener, state: Arc<State>, endpoint: Arc<Endpoint>) -> bool {
+    let mut shutdown = state.shutdown.clone();
+    loop {
+        let accepted = select! {
+            x = listener.accept() => x,
+            _ = shutdown.wait_for(|x| *x) => return true,
+        };
+        let (stream, peer) = match accepted {
+            Ok(x) => x,
+            Err(why) => {
+                error!("Listener failure: {why}");
+                return false;
             }
-            .0,
-        )))
-        .unwrap())
+        };
+        spawn(accept(
+            stream,
+            peer.map(|x| x.ip()),
+            state.clone(),
+            endpoint.clone(),
+        ));
+    }
+}
+
+/// Find out who's on the other end of a new connection, then serve it.
+async fn accept(
+    mut stream: Stream,
+    peer: Option<IpAddr>,
+    state: Arc<State>,
+    endpoint: Arc<Endpoint>,
+) {
+    let peer = match endpoint.proxies.accept(&mut stream, peer).await {
+        Ok(x) => x,
+        Err(why) => return debug!("Bad PROXY protocol header: {why}"),
+    };
+    let Some(permit) = admit(&state, endpoint.proxies.capped(peer)) else {
+        return;
+    };
+    match &endpoint.tls {
+        Some(acceptor) => match acceptor.accept(stream).await {
+            Ok(tls) => connection(TokioIo::new(tls), state, endpoint, peer, permit).await,
+            Err(why) => debug!("TLS handshake failed: {why}"),
+        },
+        None => connection(TokioIo::new(stream), state, endpoint, peer, permit).await,
+    }
+}
+
+/// Serve HTTP on a connection from `peer`.
+async fn connection(
+    io: impl Read + Write + Unpin + Send + 'static,
+    state: Arc<State>,
+    endpoint: Arc<Endpoint>,
+    peer: Option<IpAddr>,
+    permit: Arc<Permit>,
+) {
+    let shutdown = state.shutdown.clone();
+    let open = state.clone();
+    open.metrics.connections.fetch_add(1, Relaxed);
+    let http2 = endpoint.http2;
+    let service = service_fn(move |mut req: Request<_>| {
+        // For WebSockets to take over.
+        req.extensions_mut().insert(permit.clone());
+        service(req, state.clone(), endpoint.clone(), peer)
+    });
+    // The auto builder ignores `http1_only` on connections with upgrades.
+    let result = match http2 {
+        true => {
+            let mut builder = auto::Builder::new(TokioExecutor::new());
+            builder.http1().timer(TokioTimer::new());
+            builder.http2().timer(TokioTimer::new());
+            let conn = builder.serve_connection_with_upgrades(io, service);
+            until_shutdown(conn, shutdown, |x| x.graceful_shutdown()).await
+        }
+        false => {
+            let conn = http1::Builder::new()
+                .timer(TokioTimer::new())
+                .serve_connection(io, service)
+                .with_upgrades();
+            until_shutdown(conn, shutdown, |x| x.graceful_shutdown())
+                .await
+                .map_err(Into::into)
+        }
+    };
+    open.metrics.connections.fetch_sub(1, Relaxed);
+    if let Err(why) = result {
+        error!("Accept failed: {why}");
+    }
+}
+
+/// Error for dev mode in builds without it.
+const NO_DEV: &str = "dev mode needs a build with the `dev` feature";
+
+/// Commented example config, printed by `print-default-config`.
+const DEFAULT_CONFIG: &str = include_str!("../example.usmg.toml");
+
+/// Print the embedded files with their sizes, plain and precompressed.
+fn routes() {
+    let size = |x: Option<&[u8]>| x.map_or("-".to_owned(), |x| x.len().to_string());
+    let client = CLIENT.iter().map(|x| {
+        let (bytes, gzip, br) = (x.file.bytes, x.file.gzip, x.file.br);
+        (format!("/client{}", x.path), bytes, gzip, br)
+    });
+    let assets = MANIFEST
+        .iter()
+        .map(|x| (format!("/assets{}", x.path), x.bytes, x.gzip, x.br));
+    let all: Vec<_> = client.chain(assets).collect();
+    let width = all.iter().map(|x| x.0.len()).max().unwrap_or_default();
+    println!(
+        "{:width$}  {:>9}  {:>9}  {:>9}",
+        "PATH", "BYTES", "GZIP", "BR"
+    );
+    for (path, bytes, gzip, br) in &all {
+        println!(
+            "{path:width$}  {:>9}  {:>9}  {:>9}",
+            bytes.len(),
+            size(*gzip),
+            size(*br)
+        );
+    }
+    let total: usize = all.iter().map(|x| x.1.len()).sum();
+    println!("{} files, {total} bytes", all.len());
+    if CLIENT.is_empty() {
+        println!("Built without the client, /play and /client are only served in dev mode");
+    }
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+/// Load `path` and everything the server would read from it on startup.
+fn check_config(path: &Path) -> Result<(), String> {
+    let config = Config::load(path)?;
+    if config.dev.enabled && !cfg!(feature = "dev") {
+        return Err(NO_DEV.into());
+    }
+    let index = files::lookup(CLIENT, "/index.html")
+        .map(|x| String::from_utf8_lossy(x.file.bytes).into_owned())
+        .unwrap_or_default();
+    Headers::new(&config.headers, &index).map_err(|why| format!("headers: {why}"))?;
+    for x in &config.https {
+        tls::Certificate::load(x.cert.clone(), x.key.clone())
+            .map_err(|why| format!("https listener on {}: {why}", x.listen.bind))?;
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
     pretty_env_logger::init();
 
-    let config: Config = {
-        let mut iter = std::env::args();
-        let exe = iter.next().unwrap_or_default();
-        let file = iter.next().unwrap_or_else(|| {
-            eprintln!("usage: {exe} <config>");
-            exit(1);
-        });
-        toml::from_str(&fs::read_to_string(file)?)?
+    let mut iter = std::env::args();
+    let exe = iter.next().unwrap_or_default();
+    let usage = || -> ! {
+        eprintln!(
+            "usage: {exe} [serve] <config> [--dev]
+       {exe} check-config <config>
+       {exe} routes
+       {exe} print-default-config
+
+Config keys can be overridden by environment variables like USMG_ROOMS__MAX_PLAYERS=4."
+        );
+        exit(1);
+    };
+    let args: Vec<_> = iter.collect();
+    let args: Vec<_> = args.iter().map(String::as_str).collect();
+    // A bare config path serves, as before there were subcommands.
+    let (command, args) = match args.first() {
+        Some(&x @ ("serve" | "check-config" | "routes" | "print-default-config")) => {
+            (x, &args[1..])
+        }
+        _ => ("serve", &args[..]),
     };
+    match (command, args) {
+        ("check-config", [path]) => match check_config(Path::new(path)) {
+            Ok(()) => println!("{path} is valid"),
+            Err(why) => {
+                eprintln!("{why}");
+                exit(1);
+            }
+        },
+        ("routes", []) => routes(),
+        ("print-default-config", []) => print!("{DEFAULT_CONFIG}"),
+        ("serve", args) => {
+            let dev = args.contains(&"--dev");
+            let [path] = args.iter().filter(|x| **x != "--dev").collect::<Vec<_>>()[..] else {
+                usage()
+            };
+            if path.starts_with('-') {
+                usage();
+            }
+            let config = Config::load(Path::new(path)).unwrap_or_else(|why| {
+                eprintln!("{why}");
+                exit(1);
+            });
+            // Before the runtime starts threads, as it changes the environment.
+            let inherited = Inherited::from_env();
+            tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?
+                .block_on(serve(config, dev, inherited))?;
+        }
+        _ => usage(),
+    }
+    Ok(())
+}
 
-    let mut handles = vec![];
+/// Run the server until SIGINT or SIGTERM.
+async fn serve(
+    mut config: Config,
+    dev: bool,
+    mut inherited: Inherited,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    config.dev.enabled |= dev;
 
-    for x in config.http {
-        let listener = TcpListener::bind(x.bind).await?;
-        let join = spawn(async move {
-            info!("Listening on (http) {}", x.bind);
+    let dev: Option<PathBuf> = match config.dev.enabled {
+        #[cfg(feature = "dev")]
+        true => Some(
+            config
+                .dev
+                .client
+                .take()
+                .unwrap_or_else(|| dev::CLIENT_DIR.into()),
+        ),
+        #[cfg(not(feature = "dev"))]
+        true => return Err(NO_DEV.into()),
+        false => None,
+    };
+    match &dev {
+        Some(x) => warn!(
+            "Serving the client from {} and assets from {SOURCE_DIR}",
+            x.display()
+        ),
+        None if CLIENT.is_empty() => warn!("Built without a client, it's only served in dev mode"),
+        None => (),
+    }
+
+    let netsim = config.netsim;
+    if netsim.enabled() {
+        warn!(
+            "Simulating {}±{}ms latency and {}% loss",
+            netsim.latency,
+            netsim.jitter,
+            netsim.loss * 100.0
+        );
+    }
+    // Inline scripts are only hashed here, changing them in dev mode needs a restart.
+    let index = match &dev {
+        Some(x) => fs::read_to_string(x.join("index.html")).unwrap_or_default(),
+        None => files::lookup(CLIENT, "/index.html")
+            .map(|x| String::from_utf8_lossy(x.file.bytes).into_owned())
+            .unwrap_or_default(),
+    };
+    let headers = Headers::new(&config.headers, &index)?;
+
+    let (stop, shutdown) = watch::channel(false);
+    let state = Arc::new(State {
+        rooms: Arc::new(Rooms::new(config.rooms)),
+        netsim,
+        #[cfg(feature = "dev")]
+        dev,
+        shutdown,
+        limits: config.limits,
+        limiter: Arc::new(Limiter::new(config.limits)),
+        headers,
+        access_log: config.log.access,
+        metrics: Metrics::default(),
+        metrics_path: config.metrics.path,
+    });
+    {
+        let rooms = state.rooms.clone();
+        spawn(async move {
+            let mut ticker = interval(rooms.cleanup_interval());
             loop {
-                let (tcp, _) = match listener.accept().await {
-                    Ok(x) => x,
-                    Err(why) => {
-                        error!("Listener failure: {why}");
-                        break;
-                    }
-                };
-                let io = TokioIo::new(tcp);
-                spawn(async move {
-                    if let Err(why) = http1::Builder::new()
-                        .timer(TokioTimer::new())
-                        .serve_connection(io, service_fn(service))
-                        .await
-                    {
-                        error!("Accept failed: {why}");
-                    }
-                });
+                ticker.tick().await;
+                rooms.cleanup();
             }
         });
-        handles.push(join);
+    }
+    {
+        let limiter = state.limiter.clone();
+        spawn(async move {
+            let mut ticker = interval(Limiter::CLEANUP_INTERVAL);
+            loop {
+                ticker.tick().await;
+                limiter.cleanup();
+            }
+        });
+    }
+    let mut listeners = JoinSet::new();
+
+    for x in config.http {
+        let listener = Listener::bind(&x.listen, &mut inherited).await?;
+        match &x.redirect {
+            Some(to) => info!("Listening on (http) {}, redirecting to {to}", x.listen.bind),
+            None => info!("Listening on (http) {}", x.listen.bind),
+        }
+        let endpoint = Endpoint {
+            http2: x.http2,
+            redirect: x.redirect,
+            tls: None,
+            proxies: Proxies::new(&x.listen),
+        };
+        listeners.spawn(listen(listener, state.clone(), Arc::new(endpoint)));
+    }
+
+    let mut certs = vec![];
+    for x in config.https {
+        let cert = Arc::new(tls::Certificate::load(x.cert, x.key)?);
+        let acceptor = tls::acceptor(cert.clone(), x.http2)?;
+        certs.push(cert);
+        let listener = Listener::bind(&x.listen, &mut inherited).await?;
+        info!("Listening on (https) {}", x.listen.bind);
+        let endpoint = Endpoint {
+            http2: x.http2,
+            redirect: None,
+            tls: Some(acceptor),
+            proxies: Proxies::new(&x.listen),
+        };
+        listeners.spawn(listen(listener, state.clone(), Arc::new(endpoint)));
     }
 
-    for x in handles {
-        x.await?;
+    for x in inherited.unused() {
+        warn!("Socket '{x}' passed by systemd isn't used by any listener");
     }
 
+    #[cfg(unix)]
+    if !certs.is_empty() {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangup = signal(SignalKind::hangup())?;
+        spawn(async move {
+            while hangup.recv().await.is_some() {
+                for x in &certs {
+                    x.reload();
+                }
+            }
+        });
+    }
+
+    // Listeners return whether they stopped because of the shutdown.
+    let mut clean = true;
+    select! {
+        _ = terminated() => info!("Shutting down, waiting up to {SHUTDOWN_TIMEOUT:?}"),
+        _ = async {
+            while let Some(x) = listeners.join_next().await {
+                clean &= x.unwrap_or(false);
+            }
+        } => error!("All listeners failed, shutting down"),
+    }
+    // Rooms go first, so sessions are still there to pass on the final state.
+    let deadline = tokio::time::Instant::now() + SHUTDOWN_TIMEOUT;
+    if timeout_at(deadline, state.rooms.shutdown()).await.is_err() {
+        return Err("timed out waiting for rooms to finish".into());
+    }
+    stop.send_replace(true);
+    while let Some(x) = listeners.join_next().await {
+        clean &= x.unwrap_or(false);
+    }
+    // Every connection and session holds a receiver, dropping them all closes the channel.
+    drop(state);
+    if timeout_at(deadline, stop.closed()).await.is_err() {
+        return Err("timed out waiting for connections to finish".into());
+    }
+    if !clean {
+        return Err("a listener failed".into());
+    }
+    info!("Shut down cleanly");
     Ok(())
 }
+
+/// Wait for SIGINT, or SIGTERM on unix.
+async fn terminated() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => select! {
+                _ = signal::ctrl_c() => (),
+                _ = term.recv() => (),
+            },
+            Err(why) => {
+                error!("Can't handle SIGTERM, only Ctrl-C shuts down gracefully: {why}");
+                let _ = signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = signal::ctrl_c().await;
+}
@@ -0,0 +1,152 @@
+//! Counters served in the Prometheus text format, see [`Metrics`](crate::config::Metrics).
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering::Relaxed},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use http_body_util::Full;
+use hyper::{
+    body::Bytes,
+    header::{CACHE_CONTROL, CONTENT_TYPE},
+    Method, Response, StatusCode,
+};
+
+use crate::rooms::Rooms;
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+const BUCKETS: [f64; 10] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0,
+];
+
+/// Common methods get their own label, the rest share one to keep the number of series bounded.
+fn method(x: &Method) -> &'static str {
+    match *x {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::OPTIONS => "OPTIONS",
+        _ => "other",
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}").unwrap();
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    /// Requests by method and status.
+    requests: Mutex<BTreeMap<(&'static str, u16), u64>>,
+    /// Requests per latency bucket, the last one is everything slower.
+    latency: [AtomicU64; BUCKETS.len() + 1],
+    /// Total latency, in microseconds.
+    latency_sum: AtomicU64,
+    /// Response body bytes.
+    sent: AtomicU64,
+    /// HTTP connections currently open, not counting upgraded ones.
+    pub connections: AtomicU64,
+}
+impl Metrics {
+    pub fn request(&self, method: &Method, status: StatusCode, bytes: u64, elapsed: Duration) {
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((self::method(method), status.as_u16()))
+            .or_default() += 1;
+        let secs = elapsed.as_secs_f64();
+        let bucket = BUCKETS
+            .iter()
+            .position(|x| secs <= *x)
+            .unwrap_or(BUCKETS.len());
+        self.latency[bucket].fetch_add(1, Relaxed);
+        self.latency_sum
+            .fetch_add(elapsed.as_micros() as u64, Relaxed);
+        self.sent.fetch_add(bytes, Relaxed);
+    }
+
+    fn render(&self, rooms: &Rooms) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "usmg_http_requests_total",
+            "counter",
+            "HTTP requests by method and status.",
+        );
+        for ((method, status), count) in self.requests.lock().unwrap().iter() {
+            writeln!(
+                out,
+                "usmg_http_requests_total{{method=\"{method}\",status=\"{status}\"}} {count}"
+            )
+            .unwrap();
+        }
+
+        header(
+            &mut out,
+            "usmg_http_request_duration_seconds",
+            "histogram",
+            "Time until the response is ready.",
+        );
+        let mut count = 0;
+        for (i, x) in self.latency.iter().enumerate() {
+            count += x.load(Relaxed);
+            let le = BUCKETS.get(i).map_or("+Inf".to_owned(), |x| x.to_string());
+            writeln!(
+                out,
+                "usmg_http_request_duration_seconds_bucket{{le=\"{le}\"}} {count}"
+            )
+            .unwrap();
+        }
+        let sum = self.latency_sum.load(Relaxed) as f64 / 1e6;
+        writeln!(out, "usmg_http_request_duration_seconds_sum {sum}").unwrap();
+        writeln!(out, "usmg_http_request_duration_seconds_count {count}").unwrap();
+
+        let (rooms, players) = rooms.counts();
+        let values = [
+            (
+                "usmg_http_response_bytes_total",
+                "counter",
+                "Bytes sent in response bodies.",
+                self.sent.load(Relaxed),
+            ),
+            (
+                "usmg_http_connections",
+                "gauge",
+                "Open HTTP connections, not counting WebSockets.",
+                self.connections.load(Relaxed),
+            ),
+            (
+                "usmg_rooms",
+                "gauge",
+                "Game rooms, including empty ones.",
+                rooms as u64,
+            ),
+            (
+                "usmg_players",
+                "gauge",
+                "Players in game rooms.",
+                players.into(),
+            ),
+        ];
+        for (name, kind, help, value) in values {
+            header(&mut out, name, kind, help);
+            writeln!(out, "{name} {value}").unwrap();
+        }
+        out
+    }
+
+    pub fn respond(&self, rooms: &Rooms) -> Response<Full<Bytes>> {
+        Response::builder()
+            .header(CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(Full::new(Bytes::from(self.render(rooms))))
+            .unwrap()
+    }
+}
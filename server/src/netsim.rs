@@ -0,0 +1,51 @@
+//! Simulated latency and packet loss, see [`Netsim`].
+
+use std::{collections::VecDeque, time::Duration};
+
+use tokio::{
+    spawn,
+    sync::mpsc::{unbounded_channel, UnboundedReceiver},
+    time::{sleep_until, Instant},
+};
+
+use crate::{config::Netsim, random};
+
+/// Relay messages from `rx` with simulated network conditions applied. Messages for which
+/// `lossy` is true may be dropped. The rest arrive in order, like over TCP.
+pub fn delay<T: Send + 'static>(
+    config: Netsim,
+    mut rx: UnboundedReceiver<T>,
+    lossy: fn(&T) -> bool,
+) -> UnboundedReceiver<T> {
+    let (tx, out) = unbounded_channel();
+    spawn(async move {
+        let mut queue: VecDeque<(Instant, T)> = VecDeque::new();
+        loop {
+            let next = queue.front().map(|x| x.0);
+            tokio::select! {
+                x = rx.recv() => {
+                    let Some(x) = x else {
+                        break;
+                    };
+                    if lossy(&x) && (random() as f64 / u64::MAX as f64) < config.loss {
+                        continue;
+                    }
+                    let jitter = match config.jitter {
+                        0 => 0,
+                        x => random() % (x + 1),
+                    };
+                    let at = Instant::now() + Duration::from_millis(config.latency + jitter);
+                    let at = next.map_or(at, |_| at.max(queue.back().unwrap().0));
+                    queue.push_back((at, x));
+                }
+                _ = sleep_until(next.unwrap_or_else(Instant::now)), if next.is_some() => {
+                    let (_, x) = queue.pop_front().unwrap();
+                    if tx.send(x).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    out
+}
@@ -0,0 +1,108 @@
+//! HTML pages rendered by the server.
+
+use std::time::Duration;
+
+use http_body_util::Full;
+use hyper::{
+    body::Bytes,
+    header::{CACHE_CONTROL, CONTENT_TYPE, RETRY_AFTER},
+    Response, StatusCode,
+};
+use maud::{html, Markup, PreEscaped, DOCTYPE};
+use protocol::RoomInfo;
+
+use crate::headers::Group;
+
+pub const STYLE: &str = include_str!("style.css");
+const TITLE: &str = "That one magic game I'm making";
+
+fn page(title: &str, body: Markup) -> Markup {
+    html! {
+        (DOCTYPE)
+        html lang="en" {
+            head {
+                meta charset="UTF-8";
+                meta name="viewport" content="width=device-width, initial-scale=1";
+                title { (title) }
+                style { (PreEscaped(STYLE)) }
+            }
+            body {
+                main { (body) }
+            }
+        }
+    }
+}
+
+fn respond(status: StatusCode, markup: Markup) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .extension(Group::Pages)
+        .header(CONTENT_TYPE, "text/html; charset=utf-8")
+        .header(CACHE_CONTROL, "no-cache")
+        .body(Full::new(Bytes::from(markup.0)))
+        .unwrap()
+}
+
+fn status(code: StatusCode, text: &str) -> Response<Full<Bytes>> {
+    let reason = code.canonical_reason().unwrap_or_default();
+    let markup = page(
+        &format!("{} | {reason}", code.as_u16()),
+        html! {
+            div.status { (code.as_u16()) }
+            h1 { (reason) }
+            p { (text) }
+            a href="/" { "Back to the main page" }
+        },
+    );
+    respond(code, markup)
+}
+
+/// Main page, listing public rooms.
+pub fn landing(rooms: &[RoomInfo]) -> Response<Full<Bytes>> {
+    let markup = page(
+        TITLE,
+        html! {
+            h1 { (TITLE) }
+            p { "A small online game running right in your browser." }
+            a.play href="/play" { "Play" }
+            @if rooms.is_empty() {
+                p { "No open rooms yet. Start playing to create one!" }
+            } @else {
+                table {
+                    @for x in rooms {
+                        tr {
+                            td { (x.name) }
+                            td { (x.players) "/" (x.max_players) }
+                        }
+                    }
+                }
+            }
+        },
+    );
+    respond(StatusCode::OK, markup)
+}
+
+pub fn not_found() -> Response<Full<Bytes>> {
+    status(
+        StatusCode::NOT_FOUND,
+        "This url could not be found on this server.",
+    )
+}
+
+/// Rate limited, see [`Limiter`](crate::limits::Limiter).
+pub fn too_many_requests(retry_after: Duration) -> Response<Full<Bytes>> {
+    let mut res = status(
+        StatusCode::TOO_MANY_REQUESTS,
+        "Slow down a little, then try again.",
+    );
+    let secs = retry_after.as_secs_f64().ceil() as u64;
+    res.headers_mut().insert(RETRY_AFTER, secs.max(1).into());
+    res
+}
+
+pub fn error() -> Response<Full<Bytes>> {
+    status(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Something went wrong on our side. Try again later.",
+    )
+}
@@ -0,0 +1,390 @@
+//! Finding out who clients are behind reverse proxies, see [`Proxy`].
+
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use hyper::{body::Body, header::FORWARDED, Request};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    time::timeout,
+};
+
+use crate::config::{Cidr, Listen, Proxy};
+
+/// Starts every PROXY protocol v2 header.
+const SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+/// Longest PROXY protocol v1 header, line break included.
+const V1_MAX: usize = 107;
+/// How long a proxy gets to send the PROXY protocol header.
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn invalid(why: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, why)
+}
+
+/// Source address from a PROXY protocol header, `None` if the proxy didn't give one.
+async fn read_header(stream: &mut (impl AsyncRead + Unpin)) -> io::Result<Option<IpAddr>> {
+    let mut start = [0; 12];
+    stream.read_exact(&mut start).await?;
+
+    if &start == SIGNATURE {
+        let mut rest = [0; 4];
+        stream.read_exact(&mut rest).await?;
+        let [command, family, len @ ..] = rest;
+        let mut addresses = vec![0; u16::from_be_bytes(len).into()];
+        stream.read_exact(&mut addresses).await?;
+        if command >> 4 != 2 {
+            return Err(invalid("unsupported PROXY protocol version"));
+        }
+        // LOCAL connections are the proxy's own, e.g. health checks.
+        if command & 0xf == 0 {
+            return Ok(None);
+        }
+        return Ok(match family >> 4 {
+            1 if addresses.len() >= 12 => {
+                Some(<[u8; 4]>::try_from(&addresses[..4]).unwrap().into())
+            }
+            2 if addresses.len() >= 36 => {
+                Some(<[u8; 16]>::try_from(&addresses[..16]).unwrap().into())
+            }
+            _ => None,
+        });
+    }
+
+    if !start.starts_with(b"PROXY ") {
+        return Err(invalid("expected a PROXY protocol header"));
+    }
+    let mut line = start.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX {
+            return Err(invalid("PROXY protocol header too long"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+    let malformed = || invalid("malformed PROXY protocol header");
+    let line = std::str::from_utf8(&line).map_err(|_| malformed())?;
+    let mut parts = line.trim_end().split(' ').skip(1);
+    match parts.next() {
+        Some("TCP4" | "TCP6") => parts
+            .next()
+            .and_then(|x| x.parse().ok())
+            .map(Some)
+            .ok_or_else(malformed),
+        Some("UNKNOWN") => Ok(None),
+        _ => Err(malformed()),
+    }
+}
+
+/// Address of a `Forwarded: for=` or `X-Forwarded-For` entry, e.g. `1.2.3.4` or `"[::1]:80"`.
+fn node(x: &str) -> Option<IpAddr> {
+    let x = x.trim().trim_matches('"');
+    if let Some(x) = x.strip_prefix('[') {
+        return x.split(']').next()?.parse().ok();
+    }
+    x.parse()
+        .ok()
+        .or_else(|| x.parse::<SocketAddr>().ok().map(|x| x.ip()))
+}
+
+/// Split `x` at every `separator` outside of `Forwarded` quoted strings, which may contain it.
+fn split_unquoted(x: &str, separator: char) -> impl Iterator<Item = &str> {
+    let (mut quoted, mut escaped) = (false, false);
+    x.split(move |c| {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            _ => return c == separator && !quoted,
+        }
+        false
+    })
+}
+
+/// Addresses a request was forwarded for, from the client to the last proxy. Unknown or
+/// obfuscated ones are `None`.
+fn hops(req: &Request<impl Body>) -> Vec<Option<IpAddr>> {
+    let values = |name: &str| {
+        req.headers()
+            .get_all(name)
+            .iter()
+            .flat_map(|x| split_unquoted(x.to_str().unwrap_or_default(), ','))
+    };
+    if req.headers().contains_key(FORWARDED) {
+        values("forwarded")
+            .map(|x| {
+                split_unquoted(x, ';')
+                    .filter_map(|x| x.split_once('='))
+                    .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                    .and_then(|(_, value)| node(value))
+            })
+            .collect()
+    } else {
+        values("x-forwarded-for").map(node).collect()
+    }
+}
+
+/// Trusted proxies of a listener.
+pub struct Proxies {
+    mode: Proxy,
+    trusted: Vec<Cidr>,
+}
+impl Proxies {
+    pub fn new(config: &Listen) -> Self {
+        Self {
+            mode: config.proxy,
+            trusted: config.trusted.clone(),
+        }
+    }
+
+    /// Whether `peer` is a proxy. Peers on unix sockets (`None`) always are.
+    fn trusts(&self, peer: Option<IpAddr>) -> bool {
+        peer.is_none_or(|x| self.trusted.iter().any(|range| range.contains(x)))
+    }
+
+    /// Client of a new connection from `peer`, read from the PROXY protocol header if enabled.
+    pub async fn accept(
+        &self,
+        stream: &mut (impl AsyncRead + Unpin),
+        peer: Option<IpAddr>,
+    ) -> io::Result<Option<IpAddr>> {
+        if self.mode != Proxy::Protocol {
+            return Ok(peer);
+        }
+        if !self.trusts(peer) {
+            return Err(io::Error::other("not a trusted proxy"));
+        }
+        let header = timeout(HEADER_TIMEOUT, read_header(stream))
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+        Ok(header.or(peer))
+    }
+
+    /// Client connection caps apply to. Proxies forwarding headers speak for many clients, so
+    /// they aren't capped.
+    pub fn capped(&self, peer: Option<IpAddr>) -> Option<IpAddr> {
+        match self.mode == Proxy::Headers && self.trusts(peer) {
+            true => None,
+            false => peer,
+        }
+    }
+
+    /// Client of a request from `peer`, read from forwarding headers if enabled.
+    pub fn client(&self, req: &Request<impl Body>, peer: Option<IpAddr>) -> Option<IpAddr> {
+        if self.mode != Proxy::Headers || !self.trusts(peer) {
+            return peer;
+        }
+        // Walk back from the nearest proxy until someone who isn't one.
+        let mut client = peer;
+        for x in hops(req).into_iter().rev() {
+            let Some(x) = x else {
+                break;
+            };
+            client = Some(x);
+            if !self.trusts(client) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn header(mut bytes: &[u8]) -> io::Result<Option<IpAddr>> {
+        read_header(&mut bytes).await
+    }
+
+    fn ip(x: &str) -> Option<IpAddr> {
+        Some(x.parse().unwrap())
+    }
+
+    /// A v2 header with `command`, `family` and `addresses`.
+    fn v2(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut x = SIGNATURE.to_vec();
+        x.extend([command, family]);
+        x.extend((addresses.len() as u16).to_be_bytes());
+        x.extend(addresses);
+        x
+    }
+
+    fn request(headers: &[(&str, &str)]) -> Request<String> {
+        let mut req = Request::get("/");
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        req.body(String::new()).unwrap()
+    }
+
+    fn proxies(trusted: &[&str]) -> Proxies {
+        Proxies {
+            mode: Proxy::Headers,
+            trusted: trusted
+                .iter()
+                .map(|x| Cidr::try_from(x.to_string()).unwrap())
+                .collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn v1() {
+        let tcp4 = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nGET /";
+        assert_eq!(header(tcp4).await.unwrap(), ip("192.0.2.1"));
+        let tcp6 = b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 443\r\n";
+        assert_eq!(header(tcp6).await.unwrap(), ip("2001:db8::1"));
+        let unknown = b"PROXY UNKNOWN ffff:f...f:ffff ffff:f...f:ffff 65535 65535\r\n";
+        assert_eq!(header(unknown).await.unwrap(), None);
+        assert_eq!(header(b"PROXY UNKNOWN\r\n").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn invalid_v1() {
+        for x in [
+            &b"PROXY TCP4 192.0.2.x 198.51.100.1 1 2\r\n"[..],
+            b"PROXY UDP4 192.0.2.1 198.51.100.1 1 2\r\n",
+            b"PROXY TCP4\r\n",
+            b"GET / HTTP/1.1\r\n\r\n",
+        ] {
+            let why = header(x).await.unwrap_err();
+            assert_eq!(why.kind(), io::ErrorKind::InvalidData, "{x:?}");
+        }
+        let long = format!("PROXY TCP4 {}\r\n", "1".repeat(100));
+        let why = header(long.as_bytes()).await.unwrap_err();
+        assert_eq!(why.to_string(), "PROXY protocol header too long");
+    }
+
+    #[tokio::test]
+    async fn truncated() {
+        let v1 = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n";
+        let mut v2_ipv4 = v2(0x21, 0x11, &[192, 0, 2, 1, 198, 51, 100, 1, 0, 1, 0, 2]);
+        for full in [&v1[..], &v2_ipv4] {
+            for len in [0, 5, 12, 14, full.len() - 1] {
+                let why = header(&full[..len]).await.unwrap_err();
+                assert_eq!(why.kind(), io::ErrorKind::UnexpectedEof, "{len}");
+            }
+        }
+        // The length says there's more than what's sent.
+        v2_ipv4[15] += 1;
+        let why = header(&v2_ipv4).await.unwrap_err();
+        assert_eq!(why.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test]
+    async fn v2_proxy() {
+        let ipv4 = v2(0x21, 0x11, &[192, 0, 2, 1, 198, 51, 100, 1, 0, 1, 0, 2]);
+        assert_eq!(header(&ipv4).await.unwrap(), ip("192.0.2.1"));
+
+        let mut addresses = vec![0; 36];
+        addresses[..16].copy_from_slice(
+            &"2001:db8::1"
+                .parse::<std::net::Ipv6Addr>()
+                .unwrap()
+                .octets(),
+        );
+        assert_eq!(
+            header(&v2(0x21, 0x21, &addresses)).await.unwrap(),
+            ip("2001:db8::1")
+        );
+
+        // TLVs after the addresses are skipped.
+        let mut tlv = ipv4.clone();
+        tlv[15] += 4;
+        tlv.extend([4, 0, 1, 0, b'G']);
+        let mut stream = &tlv[..];
+        assert_eq!(read_header(&mut stream).await.unwrap(), ip("192.0.2.1"));
+        assert_eq!(stream, b"G");
+    }
+
+    #[tokio::test]
+    async fn v2_without_address() {
+        // LOCAL, e.g. a health check by the proxy itself.
+        let local = v2(0x20, 0x11, &[192, 0, 2, 1, 198, 51, 100, 1, 0, 1, 0, 2]);
+        assert_eq!(header(&local).await.unwrap(), None);
+        // UNSPEC and unix families.
+        assert_eq!(header(&v2(0x21, 0x00, &[])).await.unwrap(), None);
+        assert_eq!(header(&v2(0x21, 0x31, &[0; 216])).await.unwrap(), None);
+        // Too short for the family.
+        assert_eq!(
+            header(&v2(0x21, 0x11, &[192, 0, 2, 1])).await.unwrap(),
+            None
+        );
+
+        let why = header(&v2(0x11, 0x11, &[])).await.unwrap_err();
+        assert_eq!(why.to_string(), "unsupported PROXY protocol version");
+    }
+
+    #[test]
+    fn forwarded_for() {
+        let proxies = proxies(&["127.0.0.1", "10.0.0.0/8"]);
+        let client = |peer, x| proxies.client(&request(&[("x-forwarded-for", x)]), ip(peer));
+        assert_eq!(client("127.0.0.1", "192.0.2.1"), ip("192.0.2.1"));
+        assert_eq!(client("127.0.0.1", "192.0.2.1, 10.0.0.2"), ip("192.0.2.1"));
+        // Clients can put anything in front, only the last untrusted address counts.
+        assert_eq!(client("127.0.0.1", "6.6.6.6, 192.0.2.1"), ip("192.0.2.1"));
+        assert_eq!(
+            client("127.0.0.1", "6.6.6.6, 127.0.0.1, 192.0.2.1, 10.0.0.2"),
+            ip("192.0.2.1")
+        );
+        // A trusted client is still the client when there's nothing before it.
+        assert_eq!(client("127.0.0.1", "10.0.0.2"), ip("10.0.0.2"));
+        // Hops that can't be read stop the walk at the last address known.
+        assert_eq!(
+            client("127.0.0.1", "6.6.6.6, garbage, 10.0.0.2"),
+            ip("10.0.0.2")
+        );
+        // Untrusted peers can't claim to be someone else.
+        assert_eq!(client("192.0.2.9", "6.6.6.6"), ip("192.0.2.9"));
+    }
+
+    #[test]
+    fn forwarded_lines() {
+        let proxies = proxies(&["127.0.0.1"]);
+        let req = request(&[
+            ("x-forwarded-for", "6.6.6.6"),
+            ("x-forwarded-for", "192.0.2.1, 127.0.0.1"),
+        ]);
+        assert_eq!(proxies.client(&req, ip("127.0.0.1")), ip("192.0.2.1"));
+        // Unix socket peers are trusted.
+        assert_eq!(proxies.client(&req, None), ip("192.0.2.1"));
+
+        let proxies = Proxies {
+            mode: Proxy::None,
+            ..proxies
+        };
+        assert_eq!(proxies.client(&req, ip("127.0.0.1")), ip("127.0.0.1"));
+    }
+
+    #[test]
+    fn forwarded() {
+        let hops = |x| {
+            hops(&request(&[
+                ("forwarded", x),
+                ("x-forwarded-for", "6.6.6.6"),
+            ]))
+        };
+        assert_eq!(hops("for=192.0.2.1"), [ip("192.0.2.1")]);
+        assert_eq!(
+            hops("For=\"[2001:db8:cafe::17]:4711\";proto=https, for=192.0.2.60;by=203.0.113.43"),
+            [ip("2001:db8:cafe::17"), ip("192.0.2.60")]
+        );
+        assert_eq!(hops("for=\"[2001:db8::1]\""), [ip("2001:db8::1")]);
+        assert_eq!(hops("for=\"192.0.2.1:80\""), [ip("192.0.2.1")]);
+        assert_eq!(
+            hops("for=unknown, for=_hidden, proto=http"),
+            [None, None, None]
+        );
+        // Quoted strings may contain separators.
+        assert_eq!(
+            hops("by=\"a,b;c\";for=192.0.2.1, for=\"_x,\\\"y\""),
+            [ip("192.0.2.1"), None]
+        );
+
+        let proxies = proxies(&["127.0.0.1"]);
+        let req = request(&[("forwarded", "for=\"[2001:db8::1]:80\", for=127.0.0.1")]);
+        assert_eq!(proxies.client(&req, ip("127.0.0.1")), ip("2001:db8::1"));
+    }
+}
@@ -0,0 +1,372 @@
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use protocol::{ErrorCode, RoomInfo};
+
+use tokio::sync::oneshot;
+
+use crate::{
+    config,
+    instance::{Command, Instance},
+    random,
+};
+
+/// Characters private room codes are made of. No `0`/`O` or `1`/`I` to avoid confusion.
+const CODE_CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LEN: usize = 6;
+/// Longest room name, in characters.
+const MAX_NAME: usize = 32;
+
+fn code() -> String {
+    let mut x = random();
+    (0..CODE_LEN)
+        .map(|_| {
+            let c = CODE_CHARS[(x % CODE_CHARS.len() as u64) as usize] as char;
+            x /= CODE_CHARS.len() as u64;
+            c
+        })
+        .collect()
+}
+
+struct Room {
+    name: String,
+    code: Option<String>,
+    max_players: u32,
+    players: u32,
+    instance: Instance,
+    /// When the last player left.
+    idle_since: Option<Instant>,
+}
+
+#[derive(Default)]
+struct Inner {
+    rooms: BTreeMap<u32, Room>,
+    next_id: u32,
+    /// Set by [`Rooms::shutdown`], no rooms are created after.
+    closed: bool,
+}
+
+/// A room the player is in.
+pub struct Joined {
+    pub room: RoomInfo,
+    pub code: Option<String>,
+    pub instance: Instance,
+}
+
+/// All rooms on the server.
+pub struct Rooms {
+    config: config::Rooms,
+    inner: Mutex<Inner>,
+}
+impl Rooms {
+    pub fn new(config: config::Rooms) -> Self {
+        Self {
+            config,
+            inner: Default::default(),
+        }
+    }
+
+    /// Public rooms.
+    pub fn list(&self) -> Vec<RoomInfo> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .rooms
+            .iter()
+            .filter(|(_, x)| x.code.is_none())
+            .map(|(id, x)| info(*id, x))
+            .collect()
+    }
+
+    /// Number of rooms, and of players in them.
+    pub fn counts(&self) -> (usize, u32) {
+        let inner = self.inner.lock().unwrap();
+        (
+            inner.rooms.len(),
+            inner.rooms.values().map(|x| x.players).sum(),
+        )
+    }
+
+    /// Create an empty room. `max_players` is clamped to the configured limit.
+    pub fn create(&self, name: &str, private: bool, max_players: u32) -> Result<u32, ErrorCode> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.closed || inner.rooms.len() >= self.config.max_rooms {
+            return Err(ErrorCode::Limit);
+        }
+        let code = private.then(|| loop {
+            let x = code();
+            if !inner.rooms.values().any(|y| y.code.as_ref() == Some(&x)) {
+                break x;
+            }
+        });
+        inner.next_id += 1;
+        let id = inner.next_id;
+        let name = match name.trim() {
+            "" => format!("Room {id}"),
+            x => x.chars().take(MAX_NAME).collect(),
+        };
+        let max_players = match max_players {
+            0 => self.config.max_players,
+            x => x.min(self.config.max_players),
+        };
+        debug!("Created room {id} '{name}'");
+        inner.rooms.insert(
+            id,
+            Room {
+                name,
+                code,
+                max_players,
+                players: 0,
+                instance: Instance::spawn(id),
+                idle_since: Some(Instant::now()),
+            },
+        );
+        Ok(id)
+    }
+
+    /// Take a slot in a room.
+    pub fn join(&self, id: u32) -> Result<Joined, ErrorCode> {
+        let mut inner = self.inner.lock().unwrap();
+        let room = inner.rooms.get_mut(&id).ok_or(ErrorCode::NotFound)?;
+        if room.players >= room.max_players {
+            return Err(ErrorCode::Full);
+        }
+        room.players += 1;
+        room.idle_since = None;
+        Ok(Joined {
+            room: info(id, room),
+            code: room.code.clone(),
+            instance: room.instance.clone(),
+        })
+    }
+
+    /// Find a private room by code, ignoring case.
+    pub fn find(&self, code: &str) -> Option<u32> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .rooms
+            .iter()
+            .find(|(_, x)| {
+                x.code
+                    .as_ref()
+                    .is_some_and(|x| x.eq_ignore_ascii_case(code))
+            })
+            .map(|(id, _)| *id)
+    }
+
+    /// Fullest public room with a free slot, or a new one. It can fill up before it's joined.
+    pub fn quick_match(&self) -> Result<u32, ErrorCode> {
+        let found = {
+            let inner = self.inner.lock().unwrap();
+            inner
+                .rooms
+                .iter()
+                .filter(|(_, x)| x.code.is_none() && x.players < x.max_players)
+                .max_by_key(|(_, x)| x.players)
+                .map(|(id, _)| *id)
+        };
+        match found {
+            Some(x) => Ok(x),
+            None => self.create("", false, 0),
+        }
+    }
+
+    /// Free a slot taken by [`Rooms::join`].
+    pub fn leave(&self, id: u32) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(x) = inner.rooms.get_mut(&id) {
+            x.players -= 1;
+            if x.players == 0 {
+                x.idle_since = Some(Instant::now());
+            }
+        }
+    }
+
+    /// Remove rooms that have been empty for longer than the idle timeout.
+    pub fn cleanup(&self) {
+        let timeout = Duration::from_secs(self.config.idle_timeout);
+        let mut inner = self.inner.lock().unwrap();
+        inner.rooms.retain(|id, x| {
+            let keep = x.idle_since.is_none_or(|x| x.elapsed() < timeout);
+            if !keep {
+                debug!("Removed idle room {id} '{}'", x.name);
+            }
+            keep
+        });
+    }
+
+    /// Close every room and wait until their players got the final state.
+    pub async fn shutdown(&self) {
+        let instances: Vec<_> = {
+            let mut inner = self.inner.lock().unwrap();
+            inner.closed = true;
+            std::mem::take(&mut inner.rooms)
+                .into_values()
+                .map(|x| x.instance)
+                .collect()
+        };
+        let mut stopped = vec![];
+        for x in instances {
+            let (tx, rx) = oneshot::channel();
+            x.send(Command::Shutdown(tx));
+            stopped.push(rx);
+        }
+        for x in stopped {
+            let _ = x.await;
+        }
+    }
+
+    /// How often [`Rooms::cleanup`] should be called.
+    pub fn cleanup_interval(&self) -> Duration {
+        Duration::from_secs(self.config.idle_timeout.clamp(1, 60))
+    }
+}
+
+fn info(id: u32, room: &Room) -> RoomInfo {
+    RoomInfo {
+        id,
+        name: room.name.clone(),
+        players: room.players,
+        max_players: room.max_players,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    fn rooms(max_rooms: usize, max_players: u32) -> Rooms {
+        Rooms::new(config::Rooms {
+            max_rooms,
+            max_players,
+            idle_timeout: 0,
+        })
+    }
+
+    #[tokio::test]
+    async fn codes() {
+        let rooms = rooms(1000, 4);
+        let mut codes = BTreeSet::new();
+        for _ in 0..500 {
+            let id = rooms.create("", true, 0).unwrap();
+            let code = rooms.join(id).unwrap().code.unwrap();
+            assert_eq!(code.len(), CODE_LEN);
+            assert!(code.bytes().all(|x| CODE_CHARS.contains(&x)));
+            assert_eq!(rooms.find(&code.to_lowercase()), Some(id));
+            assert!(codes.insert(code));
+        }
+        assert_eq!(rooms.find("AAAAAA"), None);
+        // Private rooms aren't listed or quick matched.
+        assert!(rooms.list().is_empty());
+        let id = rooms.quick_match().unwrap();
+        assert_eq!(rooms.join(id).unwrap().code, None);
+        assert_eq!(rooms.list().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn names() {
+        let rooms = rooms(4, 4);
+        let id = rooms.create("  ", false, 0).unwrap();
+        assert_eq!(rooms.list()[0].name, format!("Room {id}"));
+        rooms.create(&"x".repeat(100), false, 0).unwrap();
+        assert_eq!(rooms.list()[1].name, "x".repeat(MAX_NAME));
+    }
+
+    #[tokio::test]
+    async fn max_players() {
+        let rooms = rooms(4, 8);
+        for (asked, got) in [(0, 8), (1, 1), (5, 5), (8, 8), (100, 8)] {
+            let id = rooms.create("", false, asked).unwrap();
+            assert_eq!(rooms.join(id).unwrap().room.max_players, got);
+            rooms.leave(id);
+            rooms.cleanup();
+        }
+    }
+
+    #[tokio::test]
+    async fn full() {
+        let rooms = rooms(1, 2);
+        let id = rooms.create("", false, 0).unwrap();
+        rooms.join(id).unwrap();
+        assert_eq!(rooms.join(id).unwrap().room.players, 2);
+        assert_eq!(rooms.join(id).err(), Some(ErrorCode::Full));
+        assert_eq!(rooms.create("", false, 0), Err(ErrorCode::Limit));
+        assert_eq!(rooms.quick_match(), Err(ErrorCode::Limit));
+        rooms.leave(id);
+        assert_eq!(rooms.quick_match(), Ok(id));
+        assert_eq!(rooms.join(42).err(), Some(ErrorCode::NotFound));
+    }
+
+    #[tokio::test]
+    async fn quick_match() {
+        let rooms = rooms(4, 3);
+        let a = rooms.quick_match().unwrap();
+        let b = rooms.create("", false, 0).unwrap();
+        rooms.join(b).unwrap();
+        assert_eq!(rooms.quick_match(), Ok(b));
+        rooms.join(a).unwrap();
+        rooms.join(a).unwrap();
+        assert_eq!(rooms.quick_match(), Ok(a));
+        rooms.join(a).unwrap();
+        assert_eq!(rooms.quick_match(), Ok(b));
+    }
+
+    #[tokio::test]
+    async fn leave_and_cleanup() {
+        let rooms = rooms(4, 4);
+        let a = rooms.create("", false, 0).unwrap();
+        let b = rooms.create("", false, 0).unwrap();
+        rooms.join(a).unwrap();
+        rooms.join(a).unwrap();
+        rooms.join(b).unwrap();
+        assert_eq!(rooms.counts(), (2, 3));
+
+        rooms.leave(a);
+        rooms.leave(b);
+        rooms.cleanup();
+        assert_eq!(rooms.counts(), (1, 1));
+        assert_eq!(rooms.list()[0].id, a);
+
+        rooms.leave(a);
+        rooms.cleanup();
+        assert_eq!(rooms.counts(), (0, 0));
+        // Leaving a removed room is harmless.
+        rooms.leave(a);
+    }
+
+    #[tokio::test]
+    async fn idle_timeout() {
+        let rooms = Rooms::new(config::Rooms {
+            idle_timeout: 60,
+            ..Default::default()
+        });
+        let id = rooms.create("", false, 0).unwrap();
+        rooms.cleanup();
+        assert_eq!(rooms.counts(), (1, 0));
+        rooms
+            .inner
+            .lock()
+            .unwrap()
+            .rooms
+            .get_mut(&id)
+            .unwrap()
+            .idle_since = Some(Instant::now() - Duration::from_secs(61));
+        rooms.cleanup();
+        assert_eq!(rooms.counts(), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn shutdown() {
+        let rooms = rooms(4, 4);
+        let id = rooms.create("", false, 0).unwrap();
+        rooms.shutdown().await;
+        assert_eq!(rooms.counts(), (0, 0));
+        assert_eq!(rooms.join(id).err(), Some(ErrorCode::NotFound));
+        assert_eq!(rooms.create("", false, 0), Err(ErrorCode::Limit));
+        assert_eq!(rooms.quick_match(), Err(ErrorCode::Limit));
+    }
+}
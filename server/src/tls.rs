@@ -0,0 +1,86 @@
+//! HTTPS certificates, reloadable without a restart.
+
+use std::{
+    error::Error,
+    fmt::Debug,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use rustls_pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+use tokio_rustls::{
+    rustls::{
+        crypto::ring,
+        server::{ClientHello, ResolvesServerCert},
+        sign::CertifiedKey,
+        ServerConfig,
+    },
+    TlsAcceptor,
+};
+
+fn read(cert: &PathBuf, key: &PathBuf) -> Result<CertifiedKey, Box<dyn Error + Send + Sync>> {
+    let chain = CertificateDer::pem_file_iter(cert)?.collect::<Result<Vec<_>, _>>()?;
+    if chain.is_empty() {
+        return Err(format!("no certificates in {}", cert.display()).into());
+    }
+    let key = ring::sign::any_supported_type(&PrivateKeyDer::from_pem_file(key)?)?;
+    Ok(CertifiedKey::new(chain, key))
+}
+
+/// Certificate chain and private key read from PEM files.
+pub struct Certificate {
+    cert: PathBuf,
+    key: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+impl Certificate {
+    pub fn load(cert: PathBuf, key: PathBuf) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let current = read(&cert, &key)
+            .map_err(|why| format!("{}: {why}", cert.display()))?
+            .into();
+        Ok(Self {
+            cert,
+            key,
+            current: RwLock::new(current),
+        })
+    }
+
+    /// Read the files again. The old certificate stays in use if that fails.
+    pub fn reload(&self) {
+        match read(&self.cert, &self.key) {
+            Ok(x) => {
+                *self.current.write().unwrap() = x.into();
+                info!("Reloaded certificate {}", self.cert.display());
+            }
+            Err(why) => error!("Failed to reload {}: {why}", self.cert.display()),
+        }
+    }
+}
+impl Debug for Certificate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Certificate")
+            .field("cert", &self.cert)
+            .field("key", &self.key)
+            .finish_non_exhaustive()
+    }
+}
+impl ResolvesServerCert for Certificate {
+    fn resolve(&self, _: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+pub fn acceptor(
+    cert: Arc<Certificate>,
+    http2: bool,
+) -> Result<TlsAcceptor, Box<dyn Error + Send + Sync>> {
+    let mut config = ServerConfig::builder_with_provider(ring::default_provider().into())
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_cert_resolver(cert);
+    config.alpn_protocols = match http2 {
+        true => vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+        false => vec![b"http/1.1".to_vec()],
+    };
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
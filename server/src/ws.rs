@@ -0,0 +1,402 @@
+use std::{
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use futures_util::{SinkExt, StreamExt};
+use http_body_util::Full;
+use hyper::{
+    body::{Body, Bytes},
+    header::{CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_VERSION, UPGRADE},
+    upgrade::Upgraded,
+    Method, Request, Response, StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use protocol::{ClientMessage, ErrorCode, Input, ServerMessage, VERSION};
+use tokio::{
+    spawn,
+    sync::{
+        mpsc::{unbounded_channel, UnboundedSender},
+        watch,
+    },
+};
+use tokio_tungstenite::{
+    tungstenite::{
+        handshake::derive_accept_key,
+        protocol::{frame::coding::CloseCode, CloseFrame, Role},
+        Message as Frame,
+    },
+    WebSocketStream,
+};
+
+use crate::{
+    config::{Netsim, Rate},
+    instance::{Command, Instance},
+    limits::{Bucket, Permit},
+    netsim,
+    rooms::Rooms,
+};
+
+type Socket = WebSocketStream<TokioIo<Upgraded>>;
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+/// Rooms tried by a quick match before creating one.
+const QUICK_MATCH_TRIES: usize = 3;
+
+fn header(req: &Request<impl Body>, name: impl hyper::header::AsHeaderName) -> &str {
+    req.headers()
+        .get(name)
+        .and_then(|x| x.to_str().ok())
+        .unwrap_or_default()
+}
+
+/// Accept a WebSocket upgrade and run the session in the background.
+pub fn upgrade(
+    req: &mut Request<impl Body>,
+    rooms: Arc<Rooms>,
+    netsim: Netsim,
+    shutdown: watch::Receiver<bool>,
+    messages: Rate,
+    client: Option<IpAddr>,
+) -> Response<Full<Bytes>> {
+    let is_upgrade = req.method() == Method::GET
+        && header(req, CONNECTION)
+            .split(',')
+            .any(|x| x.trim().eq_ignore_ascii_case("upgrade"))
+        && header(req, UPGRADE).eq_ignore_ascii_case("websocket")
+        && header(req, SEC_WEBSOCKET_VERSION) == "13";
+    let key = header(req, SEC_WEBSOCKET_KEY);
+    if !is_upgrade || key.is_empty() {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header(SEC_WEBSOCKET_VERSION, "13")
+            .body(Full::new(Bytes::from_static(
+                b"Expected a WebSocket upgrade",
+            )))
+            .unwrap();
+    }
+    let accept = derive_accept_key(key.as_bytes());
+
+    // The connection stays counted against its client for as long as the session lasts.
+    let permit = req.extensions_mut().remove::<Arc<Permit>>();
+    let upgrade = hyper::upgrade::on(req);
+    spawn(async move {
+        let _permit = permit;
+        match upgrade.await {
+            Ok(x) => {
+                let socket =
+                    WebSocketStream::from_raw_socket(TokioIo::new(x), Role::Server, None).await;
+                session(socket, rooms, netsim, shutdown, messages, client).await;
+            }
+            Err(why) => error!("Upgrade failed: {why}"),
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(CONNECTION, "Upgrade")
+        .header(UPGRADE, "websocket")
+        .header(SEC_WEBSOCKET_ACCEPT, accept)
+        .body(Full::default())
+        .unwrap()
+}
+
+async fn send(socket: &mut Socket, message: &ServerMessage) -> bool {
+    socket
+        .send(Frame::Binary(protocol::encode(message)))
+        .await
+        .is_ok()
+}
+
+fn refusal(code: ErrorCode) -> ServerMessage {
+    let message = match code {
+        ErrorCode::NotFound => "no such room",
+        ErrorCode::Full => "room is full",
+        ErrorCode::Limit => "too many rooms",
+        ErrorCode::NotHost => "only the host can do that",
+        _ => "request refused",
+    };
+    ServerMessage::Error {
+        code,
+        message: message.into(),
+    }
+}
+
+/// Player `id` in a room, if any.
+struct Session {
+    id: u32,
+    tx: UnboundedSender<ServerMessage>,
+    room: Option<(u32, Instance)>,
+}
+impl Session {
+    fn leave(&mut self, rooms: &Rooms) {
+        if let Some((room, instance)) = self.room.take() {
+            instance.send(Command::Leave(self.id));
+            rooms.leave(room);
+        }
+    }
+
+    /// Free the slot after the instance removed the player on its own.
+    fn kicked(&mut self, rooms: &Rooms) {
+        if let Some((room, _)) = self.room.take() {
+            rooms.leave(room);
+        }
+    }
+
+    /// Move to another room, replying with the refusal on failure.
+    fn switch(&mut self, rooms: &Rooms, target: Result<u32, ErrorCode>) -> Option<ServerMessage> {
+        target.and_then(|x| self.join(rooms, x)).err().map(refusal)
+    }
+
+    /// Join the fullest public room. Others may fill it first, then the next one is tried.
+    fn quick_match(&mut self, rooms: &Rooms) -> Option<ServerMessage> {
+        for _ in 0..QUICK_MATCH_TRIES {
+            match rooms.quick_match().and_then(|x| self.join(rooms, x)) {
+                Err(ErrorCode::Full) => continue,
+                x => return x.err().map(refusal),
+            }
+        }
+        // Rooms fill up faster than this player gets in, so it gets one of its own.
+        self.switch(rooms, rooms.create("", false, 0))
+    }
+
+    /// Move to room `target`. Stays in the current one on failure.
+    fn join(&mut self, rooms: &Rooms, target: u32) -> Result<(), ErrorCode> {
+        if self.room.as_ref().is_some_and(|x| x.0 == target) {
+            return Ok(());
+        }
+        let joined = rooms.join(target)?;
+        self.leave(rooms);
+        joined.instance.send(Command::Join {
+            id: self.id,
+            tx: self.tx.clone(),
+            room: joined.room,
+            code: joined.code,
+        });
+        self.room = Some((target, joined.instance));
+        Ok(())
+    }
+}
+
+async fn session(
+    mut socket: Socket,
+    rooms: Arc<Rooms>,
+    netsim: Netsim,
+    mut shutdown: watch::Receiver<bool>,
+    messages: Rate,
+    client: Option<IpAddr>,
+) {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    match client {
+        Some(x) => debug!("Client {id} connected from {x}"),
+        None => debug!("Client {id} connected"),
+    }
+    let (tx, mut rx) = unbounded_channel();
+    // With simulated network conditions, inputs take a detour through a delayed channel.
+    let mut inputs = None;
+    if netsim.enabled() {
+        rx = netsim::delay(netsim, rx, |x| {
+            matches!(x, ServerMessage::Snapshot(_) | ServerMessage::Delta(_))
+        });
+        let (tx, rx) = unbounded_channel::<(Instance, Input)>();
+        let mut rx = netsim::delay(netsim, rx, |_| true);
+        spawn(async move {
+            while let Some((instance, x)) = rx.recv().await {
+                instance.send(Command::Input(id, x));
+            }
+        });
+        inputs = Some(tx);
+    }
+    let mut session = Session { id, tx, room: None };
+    let mut welcomed = false;
+    let mut close = None;
+    let mut bucket = Bucket::new(messages);
+
+    loop {
+        let bytes = tokio::select! {
+            frame = socket.next() => match frame {
+                Some(Ok(Frame::Close(_))) | None => break,
+                Some(Ok(_)) if bucket.take(messages).is_err() => {
+                    warn!("Client {id}: too many messages");
+                    close = Some(CloseFrame {
+                        code: CloseCode::Policy,
+                        reason: "too many messages".into(),
+                    });
+                    break;
+                }
+                Some(Ok(Frame::Binary(x))) => x,
+                Some(Ok(_)) => continue,
+                Some(Err(why)) => {
+                    warn!("Client {id}: {why}");
+                    break;
+                }
+            },
+            message = rx.recv() => match message {
+                // Kicked from a room it has left since, by its own request.
+                Some(ServerMessage::Kicked(x)) if session.room.as_ref().is_none_or(|y| y.0 != x) => {
+                    continue
+                }
+                Some(x) => {
+                    if let ServerMessage::Kicked(room) = x {
+                        debug!("Client {id}: kicked from room {room}");
+                        session.kicked(&rooms);
+                    }
+                    match send(&mut socket, &x).await {
+                        true => continue,
+                        false => break,
+                    }
+                }
+                None => break,
+            },
+            // The returned guard isn't `Send`, drop it right away.
+            _ = async { drop(shutdown.wait_for(|x| *x).await) } => {
+                // The final state of its room may still be on the way.
+                while let Ok(x) = rx.try_recv() {
+                    if !send(&mut socket, &x).await {
+                        break;
+                    }
+                }
+                close = Some(CloseFrame {
+                    code: CloseCode::Away,
+                    reason: "server is shutting down".into(),
+                });
+                break;
+            }
+        };
+
+        let error = |code, message: String| Some(ServerMessage::Error { code, message });
+        let instance = session.room.as_ref().map(|x| &x.1);
+        let reply = match protocol::decode(&bytes) {
+            Ok(ClientMessage::Hello { .. }) if welcomed => {
+                error(ErrorCode::Unexpected, "already greeted".into())
+            }
+            Ok(ClientMessage::Hello { version, name }) if version == VERSION => {
+                debug!("Client {id} is '{name}'");
+                welcomed = true;
+                Some(ServerMessage::Welcome { id })
+            }
+            Ok(ClientMessage::Hello { version, .. }) => error(
+                ErrorCode::Version,
+                format!("protocol version {version} is not supported (expected {VERSION})"),
+            ),
+            Ok(_) if !welcomed => error(ErrorCode::Unexpected, "expected a handshake".into()),
+            Ok(ClientMessage::Input(x)) => {
+                match (instance, &inputs) {
+                    (Some(instance), Some(inputs)) => {
+                        let _ = inputs.send((instance.clone(), x));
+                    }
+                    (Some(instance), None) => instance.send(Command::Input(id, x)),
+                    (None, _) => (),
+                }
+                None
+            }
+            Ok(ClientMessage::Chat(x)) => {
+                if let Some(instance) = instance {
+                    instance.send(Command::Chat(id, x));
+                }
+                None
+            }
+            Ok(ClientMessage::Ping(x)) => Some(ServerMessage::Pong(x)),
+            Ok(ClientMessage::Pong(_)) => None,
+            Ok(ClientMessage::ListRooms) => Some(ServerMessage::Rooms(rooms.list())),
+            Ok(ClientMessage::CreateRoom {
+                name,
+                private,
+                max_players,
+            }) => session.switch(&rooms, rooms.create(&name, private, max_players)),
+            Ok(ClientMessage::JoinRoom(x)) => session.switch(&rooms, Ok(x)),
+            Ok(ClientMessage::JoinCode(x)) => {
+                session.switch(&rooms, rooms.find(&x).ok_or(ErrorCode::NotFound))
+            }
+            Ok(ClientMessage::QuickMatch) => session.quick_match(&rooms),
+            Ok(ClientMessage::LeaveRoom) => {
+                session.leave(&rooms);
+                Some(ServerMessage::Left)
+            }
+            Ok(ClientMessage::Kick(x)) => match instance {
+                Some(instance) => {
+                    instance.send(Command::Kick { by: id, id: x });
+                    None
+                }
+                None => Some(refusal(ErrorCode::NotHost)),
+            },
+            Err(why) => error(ErrorCode::Malformed, why.to_string()),
+        };
+        let Some(reply) = reply else {
+            continue;
+        };
+        let fatal = match &reply {
+            ServerMessage::Error { code, message } if code.is_fatal() => {
+                warn!("Client {id}: {message}");
+                true
+            }
+            ServerMessage::Error { message, .. } => {
+                debug!("Client {id}: {message}");
+                false
+            }
+            _ => false,
+        };
+        if !send(&mut socket, &reply).await || fatal {
+            break;
+        }
+    }
+
+    session.leave(&rooms);
+    let _ = socket.close(close).await;
+    debug!("Client {id} disconnected");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::{sync::mpsc::UnboundedReceiver, time::timeout};
+
+    use super::*;
+    use crate::config;
+
+    fn session(id: u32) -> (Session, UnboundedReceiver<ServerMessage>) {
+        let (tx, rx) = unbounded_channel();
+        (Session { id, tx, room: None }, rx)
+    }
+
+    async fn kicked(rx: &mut UnboundedReceiver<ServerMessage>) -> u32 {
+        let wait = async {
+            loop {
+                if let Some(ServerMessage::Kicked(x)) = rx.recv().await {
+                    return x;
+                }
+            }
+        };
+        timeout(Duration::from_secs(5), wait)
+            .await
+            .expect("not kicked")
+    }
+
+    #[tokio::test]
+    async fn kick_frees_slot() {
+        let rooms = Rooms::new(config::Rooms {
+            max_rooms: 4,
+            max_players: 2,
+            idle_timeout: 0,
+        });
+        let room = rooms.create("", false, 0).unwrap();
+        let (mut host, _host) = session(1);
+        let (mut other, mut rx) = session(2);
+        let (mut third, _third) = session(3);
+        host.join(&rooms, room).unwrap();
+        other.join(&rooms, room).unwrap();
+        assert_eq!(third.join(&rooms, room), Err(ErrorCode::Full));
+
+        let instance = host.room.as_ref().unwrap().1.clone();
+        instance.send(Command::Kick { by: 1, id: 2 });
+        assert_eq!(kicked(&mut rx).await, room);
+        other.kicked(&rooms);
+        assert!(other.room.is_none());
+        assert_eq!(rooms.counts(), (1, 1));
+        third.join(&rooms, room).unwrap();
+    }
+}
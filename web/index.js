@@ -1,13 +1,13 @@
 let fidx = 0;
 
-window.load_image = path => new Promise((res, rej) => {
+window.load_image = (path, hash) => new Promise((res, rej) => {
     const image = document.createElement('img');
-    image.src = `/assets${path}`;
+    image.src = `/assets/${hash}${path}`;
     image.onload = () => res(image);
     image.onerror = err => rej(err);
 });
-window.load_font = async path => {
-    const font = new FontFace(`loaded_font_${fidx++}`, `url(/assets${path})`);
+window.load_font = async (path, hash) => {
+    const font = new FontFace(`loaded_font_${fidx++}`, `url(/assets/${hash}${path})`);
     await font.load();
     document.fonts.add(font);
     return font;
@@ -24,6 +24,7 @@ window.addEventListener('resize', () => {
 let previous = document.timeline.currentTime || performance.now();
 function loop(time) {
     const delta = time - previous;
+    previous = time;
     app.tick(delta / 1000);
     requestAnimationFrame(loop);
 }
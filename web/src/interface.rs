@@ -5,14 +5,16 @@ use std::{
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use app::{Event, Interface, Rgba};
+use app::{Control, Event, Interface, KeyState, Rgba};
 use js_sys::Object;
+use protocol::ClientMessage;
 use wasm_bindgen::prelude::*;
 use web_sys::{
-    window, CanvasRenderingContext2d, FontFace, HtmlCanvasElement, HtmlImageElement,
-    HtmlStyleElement,
+    window, CanvasRenderingContext2d, FontFace, HtmlCanvasElement, HtmlImageElement, KeyboardEvent,
 };
 
+use crate::net::Socket;
+
 fn perf_to_system(amt: f64) -> SystemTime {
     UNIX_EPOCH + Duration::from_secs_f64(amt / 1000.0)
 }
@@ -21,6 +23,16 @@ fn now() -> SystemTime {
     perf_to_system(window().unwrap().performance().unwrap().now())
 }
 
+fn control(code: &str) -> Option<Control> {
+    match code {
+        "KeyW" | "ArrowUp" => Some(Control::Up),
+        "KeyS" | "ArrowDown" => Some(Control::Down),
+        "KeyA" | "ArrowLeft" => Some(Control::Left),
+        "KeyD" | "ArrowRight" => Some(Control::Right),
+        _ => None,
+    }
+}
+
 pub struct WebInterface {
     canvas: HtmlCanvasElement,
     ctx: CanvasRenderingContext2d,
@@ -28,6 +40,8 @@ pub struct WebInterface {
     pub(crate) events: Rc<RefCell<VecDeque<Event<Self>>>>,
     size: app::ScreenSize,
     fill_style: Rgba,
+    socket: Option<Socket>,
+    _on_key: [Closure<dyn FnMut(KeyboardEvent)>; 2],
 }
 impl WebInterface {
     pub fn new() -> Self {
@@ -35,11 +49,11 @@ impl WebInterface {
         let document = window.document().unwrap();
         let body = document.body().unwrap();
 
-        let style: HtmlStyleElement = document.create_element("style").unwrap().unchecked_into();
-        style.set_inner_html("canvas{position:absolute;inset:0;image-rendering:pixelated}");
-        body.append_child(&style).unwrap();
-
         let canvas: HtmlCanvasElement = document.create_element("canvas").unwrap().unchecked_into();
+        // Through the CSSOM, since the CSP doesn't allow inline styles.
+        canvas
+            .style()
+            .set_css_text("position:absolute;inset:0;image-rendering:pixelated");
         canvas.set_width(window.inner_width().unwrap().as_f64().unwrap() as u32 + 1);
         canvas.set_height(window.inner_height().unwrap().as_f64().unwrap() as u32 + 1);
         body.append_child(&canvas).unwrap();
@@ -60,6 +74,26 @@ impl WebInterface {
 
         let events = Rc::new(RefCell::new(VecDeque::new()));
         events.borrow_mut().push_back(Event::Redraw(0.0));
+        let on_key = [
+            ("keydown", KeyState::Pressed),
+            ("keyup", KeyState::Released),
+        ]
+        .map(|(name, state)| {
+            let events = events.clone();
+            let closure = Closure::<dyn FnMut(KeyboardEvent)>::new(move |x: KeyboardEvent| {
+                if let Some(x) = control(&x.code()).filter(|_| !x.repeat()) {
+                    events.borrow_mut().push_back(Event::Control(x, state));
+                }
+            });
+            window
+                .add_event_listener_with_callback(name, closure.as_ref().unchecked_ref())
+                .unwrap_throw();
+            closure
+        });
+
+        let socket = Socket::connect(events.clone())
+            .inspect_err(|why| error!("Failed to connect to server: {why:?}"))
+            .ok();
 
         Self {
             ctx,
@@ -68,6 +102,8 @@ impl WebInterface {
             time: now(),
             events,
             fill_style: 0.into(),
+            socket,
+            _on_key: on_key,
         }
     }
 
@@ -78,6 +114,44 @@ impl WebInterface {
         self.ctx.set_image_smoothing_enabled(false);
     }
 
+    /// Draw the loading screen. `progress` is in `0.0..=1.0`.
+    pub fn draw_loading(&self, text: &str, progress: f64) {
+        let (width, height) = (self.size.0 as f64, self.size.1 as f64);
+        let bar = (width / 2.0).min(400.0);
+
+        self.ctx.save();
+        self.ctx.set_fill_style_str("#000");
+        self.ctx.fill_rect(0.0, 0.0, width, height);
+        self.ctx.set_fill_style_str("#444");
+        self.ctx
+            .fill_rect((width - bar) / 2.0, height / 2.0, bar, 8.0);
+        self.ctx.set_fill_style_str("#fff");
+        self.ctx
+            .fill_rect((width - bar) / 2.0, height / 2.0, bar * progress, 8.0);
+        self.ctx.set_font("16px sans-serif");
+        self.ctx.set_text_align("center");
+        self.ctx
+            .fill_text(text, width / 2.0, height / 2.0 - 16.0)
+            .unwrap_throw();
+        self.ctx.restore();
+    }
+
+    /// Replace the loading screen with an error.
+    pub fn draw_error(&self, text: &str) {
+        let (width, height) = (self.size.0 as f64, self.size.1 as f64);
+
+        self.ctx.save();
+        self.ctx.set_fill_style_str("#000");
+        self.ctx.fill_rect(0.0, 0.0, width, height);
+        self.ctx.set_fill_style_str("#f44");
+        self.ctx.set_font("16px sans-serif");
+        self.ctx.set_text_align("center");
+        self.ctx
+            .fill_text(text, width / 2.0, height / 2.0)
+            .unwrap_throw();
+        self.ctx.restore();
+    }
+
     pub fn pre_update(&mut self) {
         self.time = now();
     }
@@ -86,11 +160,11 @@ impl WebInterface {
         if self.fill_style == rgba {
             return;
         }
-        self.ctx.set_fill_style(&JsValue::from_str(&format!(
+        self.ctx.set_fill_style_str(&format!(
             "#{:01$x}",
             u32::from_be_bytes([rgba.0, rgba.1, rgba.2, rgba.3]),
             8
-        )));
+        ));
         self.fill_style = rgba;
     }
 }
@@ -125,6 +199,12 @@ impl Interface for WebInterface {
         60
     }
 
+    fn send(&mut self, message: ClientMessage) {
+        if let Some(x) = &self.socket {
+            x.send(message);
+        }
+    }
+
     fn clear_raw(&mut self, color: app::Rgba) {
         self.update_fill_style(0.into());
         self.ctx
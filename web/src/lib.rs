@@ -1,15 +1,22 @@
+#[macro_use]
+extern crate log;
+
 mod interface;
+mod loader;
+mod net;
 
-use app::Application;
-use assets::include_resources;
+use app::{data::Data, Application};
+use assets::{include_resources, lang::Locale};
 use interface::WebInterface;
+use loader::Loader;
 use wasm_bindgen::prelude::*;
-use web_sys::{FontFace, HtmlImageElement};
 
 #[wasm_bindgen]
 extern "C" {
-    async fn load_image(s: &str) -> HtmlImageElement;
-    async fn load_font(s: &str) -> FontFace;
+    #[wasm_bindgen(catch)]
+    async fn load_image(path: &str, hash: &str) -> Result<JsValue, JsValue>;
+    #[wasm_bindgen(catch)]
+    async fn load_font(path: &str, hash: &str) -> Result<JsValue, JsValue>;
 }
 
 #[wasm_bindgen]
@@ -31,24 +38,45 @@ impl AppWrap {
     }
 }
 
+/// Fails if some resources couldn't be loaded, after showing an error.
 #[wasm_bindgen]
-pub async fn init() -> AppWrap {
+pub async fn init() -> Result<AppWrap, JsValue> {
     console_log::init_with_level(log::Level::Trace).unwrap_throw();
 
+    let locale = web_sys::window()
+        .and_then(|x| x.navigator().language())
+        .and_then(|x| Locale::from_code(&x))
+        .unwrap_or_default();
+    let interface = WebInterface::new();
+
+    // Everything is requested at once, then awaited while showing progress.
+    let loader = Loader::default();
+    let mut progress = |loaded: usize, total: usize| {
+        let percent = (loaded * 100).checked_div(total).unwrap_or(100);
+        interface.draw_loading(&locale.loading(percent), percent as f64 / 100.0);
+    };
+    let failed = |count: usize| {
+        interface.draw_error(&locale.load_failed(count as u64));
+        JsValue::from_str(&format!("failed to load {count} resources"))
+    };
     let resources = include_resources! {
-        x.png => load_image(x.path),
-        x.ttf => load_font(x.path),
+        x.png => loader.spawn(x, load_image),
+        x.ttf => loader.spawn(x, load_font),
+        x.data => Data::load(x.path, x.mime, x.bytes).unwrap_throw(),
 
         +{
-            x.png => x.await,
-            x.ttf => x.await,
+            x.png => loader.wait(x, &mut progress).await.map_err(failed)?,
+            x.ttf => loader.wait(x, &mut progress).await.map_err(failed)?,
+            x.data => x,
         }
     };
-    let interface = WebInterface::new();
 
-    AppWrap(Application {
+    let mut app = Application {
         interface,
         resources,
         game: Default::default(),
-    })
+    };
+    app.game.set_locale(locale);
+
+    Ok(AppWrap(app))
 }
@@ -0,0 +1,110 @@
+//! Concurrent resource loading with progress and retries.
+
+use std::{
+    cell::{Cell, RefCell},
+    future::Future,
+    rc::Rc,
+};
+
+use assets::Abstract;
+use js_sys::Promise;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::window;
+
+/// Delay before the first retry. Doubled on every failure up to [`MAX_RETRY_DELAY`].
+const RETRY_DELAY: i32 = 250;
+const MAX_RETRY_DELAY: i32 = 8000;
+/// Attempts before a resource is given up on, about 40s with the delays above.
+const MAX_ATTEMPTS: u32 = 8;
+/// How often the loading screen is redrawn.
+const PROGRESS_DELAY: i32 = 50;
+
+async fn sleep(ms: i32) {
+    let promise = Promise::new(&mut |res, _| {
+        window()
+            .unwrap()
+            .set_timeout_with_callback_and_timeout_and_arguments_0(&res, ms)
+            .unwrap_throw();
+    });
+    JsFuture::from(promise).await.unwrap_throw();
+}
+
+/// A resource that is still being loaded.
+pub struct Slot<T>(Rc<RefCell<Option<T>>>);
+
+/// Loads resources in the background, keeping track of loaded and total bytes.
+#[derive(Default)]
+pub struct Loader {
+    loaded: Rc<Cell<usize>>,
+    total: Cell<usize>,
+    pending: Rc<Cell<usize>>,
+    failed: Rc<Cell<usize>>,
+}
+impl Loader {
+    /// Start loading a resource, retrying up to [`MAX_ATTEMPTS`] times.
+    pub fn spawn<T: JsCast + 'static, F: Future<Output = Result<JsValue, JsValue>>>(
+        &self,
+        x: Abstract,
+        load: impl Fn(&'static str, &'static str) -> F + 'static,
+    ) -> Slot<T> {
+        self.total.set(self.total.get() + x.bytes.len());
+        self.pending.set(self.pending.get() + 1);
+
+        let slot = Rc::new(RefCell::new(None));
+        let (loaded, pending, failed, value) = (
+            self.loaded.clone(),
+            self.pending.clone(),
+            self.failed.clone(),
+            slot.clone(),
+        );
+        spawn_local(async move {
+            let mut delay = RETRY_DELAY;
+            for attempt in 1..=MAX_ATTEMPTS {
+                match load(x.path, x.hash).await {
+                    Ok(x) => {
+                        *value.borrow_mut() = Some(x.unchecked_into());
+                        break;
+                    }
+                    Err(why) if attempt == MAX_ATTEMPTS => {
+                        error!(
+                            "Failed to load '{}' after {attempt} attempts: {why:?}",
+                            x.path
+                        );
+                        failed.set(failed.get() + 1);
+                    }
+                    Err(why) => {
+                        warn!(
+                            "Failed to load '{}', retrying in {delay}ms: {why:?}",
+                            x.path
+                        );
+                        sleep(delay).await;
+                        delay = (delay * 2).min(MAX_RETRY_DELAY);
+                    }
+                }
+            }
+            loaded.set(loaded.get() + x.bytes.len());
+            pending.set(pending.get() - 1);
+        });
+        Slot(slot)
+    }
+
+    /// Wait for every resource to load and take this one.
+    ///
+    /// `progress` is called with loaded and total bytes while waiting. Fails with the number of
+    /// resources that were given up on, if any.
+    pub async fn wait<T>(
+        &self,
+        slot: Slot<T>,
+        progress: &mut impl FnMut(usize, usize),
+    ) -> Result<T, usize> {
+        while self.pending.get() > 0 {
+            progress(self.loaded.get(), self.total.get());
+            sleep(PROGRESS_DELAY).await;
+        }
+        match self.failed.get() {
+            0 => Ok(slot.0.take().unwrap()),
+            n => Err(n),
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use app::Event;
+use js_sys::{ArrayBuffer, Uint8Array};
+use protocol::ClientMessage;
+use wasm_bindgen::prelude::*;
+use web_sys::{window, BinaryType, MessageEvent, WebSocket};
+
+use crate::interface::WebInterface;
+
+type Events = Rc<RefCell<VecDeque<Event<WebInterface>>>>;
+
+/// WebSocket connection to the server the page was loaded from.
+pub struct Socket {
+    socket: WebSocket,
+    _on_open: Closure<dyn FnMut()>,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    _on_close: Closure<dyn FnMut()>,
+}
+impl Socket {
+    pub fn connect(events: Events) -> Result<Self, JsValue> {
+        let location = window().unwrap().location();
+        let scheme = match location.protocol()?.as_str() {
+            "https:" => "wss:",
+            _ => "ws:",
+        };
+        let socket = WebSocket::new(&format!("{scheme}//{}{}", location.host()?, protocol::PATH))?;
+        socket.set_binary_type(BinaryType::Arraybuffer);
+
+        let on_open = {
+            let events = events.clone();
+            Closure::<dyn FnMut()>::new(move || events.borrow_mut().push_back(Event::Connected))
+        };
+        let on_message = {
+            let events = events.clone();
+            Closure::new(move |x: MessageEvent| {
+                let Ok(data) = x.data().dyn_into::<ArrayBuffer>() else {
+                    return;
+                };
+                match protocol::decode(&Uint8Array::new(&data).to_vec()) {
+                    Ok(x) => events.borrow_mut().push_back(Event::Message(x)),
+                    Err(why) => warn!("Bad message from server: {why}"),
+                }
+            })
+        };
+        let on_close =
+            Closure::<dyn FnMut()>::new(move || events.borrow_mut().push_back(Event::Disconnected));
+        socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+
+        Ok(Self {
+            socket,
+            _on_open: on_open,
+            _on_message: on_message,
+            _on_close: on_close,
+        })
+    }
+
+    pub fn send(&self, message: ClientMessage) {
+        if self.socket.ready_state() == WebSocket::OPEN {
+            if let Err(why) = self.socket.send_with_u8_array(&protocol::encode(&message)) {
+                warn!("Failed to send message: {why:?}");
+            }
+        }
+    }
+}
@@ -12,6 +12,8 @@ export default {
     output: {
         path: resolve(import.meta.dirname, 'dist'),
         filename: 'index.js',
+        // The client is also served on `/play`, so it can't rely on relative paths.
+        publicPath: '/client/',
     },
     plugins: [
         new HtmlWebpackPlugin({